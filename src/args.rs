@@ -1,16 +1,441 @@
 use std::path::PathBuf;
 
-use clap::Parser;
+use clap::builder::{PossibleValue, RangedU64ValueParser};
+use clap::{Args as ClapArgs, Parser, Subcommand, ValueEnum};
+
+use crate::email::SmtpSecurity;
+use crate::event::Event;
+use crate::format::Format;
+use crate::mode::{GiftMode, Sampling};
 
 /// Program to generate random gift assignments, with or without groups.
 #[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
+#[command(
+    author,
+    version,
+    about,
+    long_about = None,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
 pub struct Args {
-    /// Input CSV file of participants
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    /// Options for `generate`, also accepted without a subcommand so
+    /// `gift_circle -i participants.csv` keeps working
+    #[command(flatten)]
+    pub generate: GenerateArgs,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Draw gift assignments (the default when no subcommand is given)
+    Generate(GenerateArgs),
+    /// Check a participants file and report every problem found
+    Validate(ValidateArgs),
+    /// Check an existing assignments file against the rules
+    Verify(VerifyArgs),
+    /// Update an earlier draw after people join or drop out, changing as little as possible
+    Repair(RepairArgs),
+    /// Summarise participants and groups, count possible circles and show how likely each
+    /// pairing is
+    Stats(StatsArgs),
+    /// Make many draws from the same input and report how evenly pairings are spread
+    Simulate(SimulateArgs),
+    /// Decrypt one encrypted assignment file
+    Reveal(RevealArgs),
+    /// Email each giver their assignment
+    Send(SendArgs),
+    /// Write a commented starter gift_circle.toml next to a participants file
+    Init(InitArgs),
+    /// Publish a commitment to the participants and rules before a verifiable draw
+    Commit(CommitArgs),
+    /// Repeat a committed draw from its revealed secret and check the published results
+    Audit(AuditArgs),
+    /// Find your recipient in a public lookup table with your private code
+    Lookup(LookupArgs),
+    /// Run a local web page and JSON API for making draws
+    #[cfg(feature = "server")]
+    Server(ServerArgs),
+}
+
+/// Options for generating gift assignments.
+#[derive(ClapArgs, Debug, Default)]
+pub struct GenerateArgs {
+    /// Settings file; defaults to gift_circle.toml in the current directory if present
+    #[arg(long, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+    #[command(flatten)]
+    pub rules: DrawRulesArgs,
+    /// Write assignments to this file instead of stdout
     #[arg(long, short, value_name = "FILE")]
-    pub input: PathBuf,
+    pub output: Option<PathBuf>,
+    /// Write one file per giver into this directory, each holding only their assignment
+    #[arg(long, value_name = "DIR")]
+    pub output_dir: Option<PathBuf>,
+    /// Also print the combined assignment list when using --output-dir
+    #[arg(long, action, overrides_with = "no_print_all")]
+    pub print_all: bool,
+    /// Don't print the combined assignment list, even if the config file says to
+    #[arg(long, action, overrides_with = "print_all")]
+    pub no_print_all: bool,
+    /// Encrypt each file in --output-dir with the giver's passphrase or public_key column
+    #[arg(long, action, overrides_with = "no_encrypt")]
+    pub encrypt: bool,
+    /// Don't encrypt, even if the config file says to
+    #[arg(long, action, overrides_with = "encrypt")]
+    pub no_encrypt: bool,
+    /// Format of the output; detected from the --output extension when omitted, else CSV
+    #[arg(long, value_enum, value_name = "FORMAT")]
+    pub output_format: Option<Format>,
+    #[arg(long, short, action, overrides_with = "no_use_groups")]
+    pub use_groups: bool,
+    /// Don't use groups, even if the config file says to
+    #[arg(long, action, overrides_with = "use_groups")]
+    pub no_use_groups: bool,
+    /// Allow several smaller loops of givers instead of one big circle
+    #[arg(long, short, action, overrides_with = "no_derangement")]
+    pub derangement: bool,
+    /// Draw one big circle, even if the config file allows several loops
+    #[arg(long, action, overrides_with = "derangement")]
+    pub no_derangement: bool,
+    /// Smallest loop allowed with --derangement; 3 or more prevents two people swapping gifts
+    #[arg(long, value_name = "PEOPLE")]
+    pub min_cycle_length: Option<usize>,
+    /// Seed for the random draw; the same seed, input and options repeat a draw exactly
+    #[arg(long, value_name = "SEED")]
+    pub seed: Option<u64>,
+    /// End CSV output with `#` comment lines recording the seed, version and mode
+    #[arg(long, action, overrides_with = "no_metadata_trailer")]
+    pub metadata_trailer: bool,
+    /// Leave out the metadata trailer, even if the config file asks for it
+    #[arg(long, action, overrides_with = "metadata_trailer")]
+    pub no_metadata_trailer: bool,
+    /// Write a public table of hashed lookup codes to recipients, and only each giver's
+    /// code into --output-dir, so nobody sees who gives to whom
+    #[arg(long, value_name = "FILE")]
+    pub lookup_table: Option<PathBuf>,
+    /// Secret written by `commit`; the seed is derived from it, the commitment and --entropy
+    #[arg(long, value_name = "FILE", conflicts_with = "seed")]
+    pub secret_file: Option<PathBuf>,
+    /// Text contributed by a participant after the commitment was published; repeat for each
+    #[arg(long, value_name = "TEXT", requires = "secret_file")]
+    pub entropy: Vec<String>,
+    /// Spending limit shown to every giver, e.g. 30
+    #[arg(long)]
+    pub budget: Option<String>,
+    /// Currency of the spending limits, e.g. USD
+    #[arg(long)]
+    pub currency: Option<String>,
+    /// When gifts are exchanged
+    #[arg(long)]
+    pub date: Option<String>,
+    /// Where gifts are exchanged
+    #[arg(long)]
+    pub location: Option<String>,
+    /// Spending limit for givers in one group instead of --budget; repeat for more groups
+    #[arg(long, value_name = "GROUP=BUDGET", value_parser = parse_group_budget)]
+    pub group_budget: Vec<(String, String)>,
+}
+
+/// Options choosing the gift mode.
+#[derive(ClapArgs, Debug)]
+pub struct ModeArgs {
     #[arg(long, short, action)]
     pub use_groups: bool,
+    /// Allow several smaller loops of givers instead of one big circle
+    #[arg(long, short, action)]
+    pub derangement: bool,
+    /// Smallest loop allowed with --derangement; 3 or more prevents two people swapping gifts
+    #[arg(
+        long,
+        value_name = "PEOPLE",
+        default_value_t = 2,
+        requires = "derangement"
+    )]
+    pub min_cycle_length: usize,
+}
+
+/// Options for checking a participants file without drawing.
+#[derive(ClapArgs, Debug)]
+pub struct ValidateArgs {
+    /// Input CSV file of participants
+    #[arg(long, short, value_name = "FILE")]
+    pub input: PathBuf,
+    /// Format of the input file; detected from its extension when omitted
+    #[arg(long, value_enum, value_name = "FORMAT")]
+    pub input_format: Option<Format>,
+    #[command(flatten)]
+    pub mode: ModeArgs,
+    #[command(flatten)]
+    pub exclusions: ExclusionArgs,
+}
+
+/// Options for checking an existing assignments file.
+#[derive(ClapArgs, Debug)]
+pub struct VerifyArgs {
+    /// Assignments file written by a previous run, as CSV or JSON
+    #[arg(long, short, value_name = "FILE")]
+    pub input: PathBuf,
+    /// Participants who must all take part; defaults to the givers in the assignments file
+    #[arg(long, short, value_name = "FILE")]
+    pub participants: Option<PathBuf>,
+    #[command(flatten)]
+    pub mode: ModeArgs,
+    /// Number of different people each participant should give a gift to
+    #[arg(
+        long,
+        short = 'k',
+        value_name = "COUNT",
+        default_value_t = 1,
+        value_parser = at_least_one()
+    )]
+    pub gifts_per_person: usize,
+    #[command(flatten)]
+    pub exclusions: ExclusionArgs,
+}
+
+/// Options for repairing an earlier draw.
+#[derive(ClapArgs, Debug)]
+pub struct RepairArgs {
+    /// Assignments file written by the earlier draw, as CSV or JSON
+    #[arg(long, short, value_name = "FILE")]
+    pub input: PathBuf,
+    /// Everyone taking part now, including newcomers and without anyone who dropped out
+    #[arg(long, short, value_name = "FILE")]
+    pub participants: PathBuf,
+    #[command(flatten)]
+    pub mode: ModeArgs,
+    #[command(flatten)]
+    pub exclusions: ExclusionArgs,
+    /// Seed for the random choices made while repairing
+    #[arg(long, value_name = "SEED")]
+    pub seed: Option<u64>,
+    /// Write the repaired assignments to this file instead of stdout
+    #[arg(long, short, value_name = "FILE")]
+    pub output: Option<PathBuf>,
+    /// Write sealed files only for the givers with a new recipient into this directory
+    #[arg(long, value_name = "DIR")]
+    pub output_dir: Option<PathBuf>,
+    /// Format of the output; detected from the --output extension when omitted, else CSV
+    #[arg(long, value_enum, value_name = "FORMAT")]
+    pub output_format: Option<Format>,
+}
+
+/// Options for summarising a participants file.
+#[derive(ClapArgs, Debug)]
+#[command(mut_arg("input", |arg| arg.required(true)))]
+pub struct StatsArgs {
+    #[command(flatten)]
+    pub rules: DrawRulesArgs,
+    #[command(flatten)]
+    pub mode: ModeArgs,
+    /// Number of draws to make when working out how likely each pairing is; 0 skips them
+    #[arg(long, value_name = "COUNT", default_value_t = 1000)]
+    pub draws: usize,
+    /// Seed for those draws, and for estimating the count for more than 16 people
+    #[arg(long, value_name = "SEED")]
+    pub seed: Option<u64>,
+    /// Only show the chances for this giver
+    #[arg(long, value_name = "NAME")]
+    pub giver: Option<String>,
+}
+
+/// Options for simulating many draws.
+#[derive(ClapArgs, Debug)]
+#[command(mut_arg("input", |arg| arg.required(true)))]
+pub struct SimulateArgs {
+    #[command(flatten)]
+    pub rules: DrawRulesArgs,
+    #[command(flatten)]
+    pub mode: ModeArgs,
+    /// Number of draws to make
+    #[arg(long, value_name = "COUNT", default_value_t = 1000)]
+    pub draws: usize,
+    /// Seed each draw's own seed is derived from; the same seed repeats the whole report
+    #[arg(long, value_name = "SEED")]
+    pub seed: Option<u64>,
+    /// Write the report to this file instead of stdout
+    #[arg(long, short, value_name = "FILE")]
+    pub output: Option<PathBuf>,
+    /// Format of the report; detected from the --output extension when omitted, else CSV
+    #[arg(long, value_enum, value_name = "FORMAT")]
+    pub output_format: Option<Format>,
+}
+
+/// Options for writing a starter config file.
+#[derive(ClapArgs, Debug)]
+pub struct InitArgs {
+    /// Participants file the config should point at
+    #[arg(long, short, value_name = "FILE")]
+    pub input: PathBuf,
+    /// Replace an existing gift_circle.toml
+    #[arg(long, action)]
+    pub force: bool,
+}
+
+/// Options for the local web server.
+#[cfg(feature = "server")]
+#[derive(ClapArgs, Debug)]
+pub struct ServerArgs {
+    /// Address and port to listen on; use 0.0.0.0 to allow other devices on the network
+    #[arg(long, value_name = "ADDRESS", default_value = "127.0.0.1:8080")]
+    pub listen: String,
+}
+
+/// Pairs that must never be assigned.
+#[derive(ClapArgs, Debug, Default)]
+pub struct ExclusionArgs {
+    /// Optional CSV file of giver,recipient pairs that must never be assigned
+    #[arg(
+        id = "exclusions",
+        long = "exclusions",
+        short = 'x',
+        value_name = "FILE"
+    )]
+    pub file: Option<PathBuf>,
+}
+
+/// The participants and rules a draw is made from, apart from the gift mode.
+#[derive(ClapArgs, Debug, Default)]
+pub struct DrawRulesArgs {
+    /// Input CSV file of participants
+    #[arg(long, short, value_name = "FILE")]
+    pub input: Option<PathBuf>,
+    /// Format of the input file; detected from its extension when omitted
+    #[arg(long, value_enum, value_name = "FORMAT")]
+    pub input_format: Option<Format>,
+    /// Number of different people each participant gives a gift to [default: 1]
+    #[arg(long, short = 'k', value_name = "COUNT", value_parser = at_least_one())]
+    pub gifts_per_person: Option<usize>,
+    #[command(flatten)]
+    pub exclusions: ExclusionArgs,
+    /// Previous output CSV whose pairs should not be repeated; repeat for more years, most recent first
+    #[arg(long, value_name = "FILE")]
+    pub history: Vec<PathBuf>,
+    /// Only avoid pairs from this many of the most recent history files
+    #[arg(long, value_name = "YEARS")]
+    pub history_years: Option<usize>,
+    /// How to pick among valid assignments; uniform makes every one equally likely [default: fast]
+    #[arg(long, value_enum, value_name = "SAMPLING")]
+    pub sampling: Option<Sampling>,
+}
+
+/// Options for committing to a draw.
+#[derive(ClapArgs, Debug)]
+#[command(mut_arg("input", |arg| arg.required(true)))]
+pub struct CommitArgs {
+    #[command(flatten)]
+    pub rules: DrawRulesArgs,
+    #[command(flatten)]
+    pub mode: ModeArgs,
+    /// Where to write the new secret; keep it private until gifts are exchanged
+    #[arg(long, value_name = "FILE")]
+    pub secret_file: PathBuf,
+    /// Replace an existing secret file
+    #[arg(long, action)]
+    pub force: bool,
+}
+
+/// Options for auditing a committed draw.
+#[derive(ClapArgs, Debug)]
+#[command(mut_arg("input", |arg| arg.required(true)))]
+pub struct AuditArgs {
+    #[command(flatten)]
+    pub rules: DrawRulesArgs,
+    #[command(flatten)]
+    pub mode: ModeArgs,
+    /// Assignments published by the organiser, as CSV or JSON
+    #[arg(long, short, value_name = "FILE")]
+    pub assignments: PathBuf,
+    /// Commitment published before the draw
+    #[arg(long, value_name = "HEX")]
+    pub commitment: String,
+    /// Secret revealed by the organiser after the exchange
+    #[arg(long)]
+    pub secret: String,
+    /// Text contributed by each participant, as used for the draw
+    #[arg(long, value_name = "TEXT")]
+    pub entropy: Vec<String>,
+}
+
+/// Options for looking up a recipient by code.
+#[derive(ClapArgs, Debug)]
+pub struct LookupArgs {
+    /// Public lookup table written with --lookup-table, as CSV or JSON
+    #[arg(long, short, value_name = "FILE")]
+    pub table: PathBuf,
+    /// Your lookup code; letters may be typed in either case, with or without dashes
+    #[arg(long, env = "GIFT_CIRCLE_LOOKUP_CODE", hide_env_values = true)]
+    pub code: String,
+}
+
+/// Options for decrypting one assignment file.
+#[derive(ClapArgs, Debug)]
+pub struct RevealArgs {
+    /// Encrypted assignment file written by --encrypt
+    #[arg(value_name = "FILE")]
+    pub file: PathBuf,
+    /// Passphrase from the participant's passphrase column
+    #[arg(long, env = "GIFT_CIRCLE_PASSPHRASE", hide_env_values = true)]
+    pub passphrase: Option<String>,
+    /// age identity file holding the secret key for the participant's public_key
+    #[arg(long, value_name = "FILE", conflicts_with = "passphrase")]
+    pub identity: Option<PathBuf>,
+}
+
+/// Options for emailing assignments to each giver.
+#[derive(ClapArgs, Debug)]
+pub struct SendArgs {
+    /// Settings file; defaults to gift_circle.toml in the current directory if present
+    #[arg(long, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+    /// Assignments file written by a previous run, as CSV or JSON
+    #[arg(long, short, value_name = "FILE")]
+    pub input: Option<PathBuf>,
+    /// Sender address, e.g. "Santa <santa@example.com>"
+    #[arg(long, env = "GIFT_CIRCLE_FROM")]
+    pub from: Option<String>,
+    /// SMTP server to deliver through [default: localhost]
+    #[arg(long, env = "GIFT_CIRCLE_SMTP_HOST")]
+    pub smtp_host: Option<String>,
+    /// SMTP port; defaults to 25, 587 or 465 depending on --smtp-security
+    #[arg(long, env = "GIFT_CIRCLE_SMTP_PORT")]
+    pub smtp_port: Option<u16>,
+    /// How to secure the SMTP connection [default: starttls]
+    #[arg(long, value_enum, env = "GIFT_CIRCLE_SMTP_SECURITY")]
+    pub smtp_security: Option<SmtpSecurity>,
+    /// SMTP user name; the password is read from GIFT_CIRCLE_SMTP_PASSWORD
+    #[arg(long, env = "GIFT_CIRCLE_SMTP_USERNAME")]
+    pub smtp_username: Option<String>,
+    /// Subject line; {name}, {email_address}, {recipient}, {budget}, {date} and {location}
+    /// are filled in per giver
+    #[arg(long)]
+    pub subject: Option<String>,
+    /// File holding the message body, with the same placeholders as --subject
+    #[arg(long, value_name = "FILE")]
+    pub body_template: Option<PathBuf>,
+    /// Spending limit shown to every giver, e.g. 30; overrides one saved with the draw
+    #[arg(long)]
+    pub budget: Option<String>,
+    /// Currency of the spending limits, e.g. USD
+    #[arg(long)]
+    pub currency: Option<String>,
+    /// When gifts are exchanged
+    #[arg(long)]
+    pub date: Option<String>,
+    /// Where gifts are exchanged
+    #[arg(long)]
+    pub location: Option<String>,
+    /// Spending limit for givers in one group instead of --budget; repeat for more groups
+    #[arg(long, value_name = "GROUP=BUDGET", value_parser = parse_group_budget)]
+    pub group_budget: Vec<(String, String)>,
+    /// Write .eml files into --eml-dir instead of sending anything
+    #[arg(long, action)]
+    pub dry_run: bool,
+    /// Directory for the .eml files written by --dry-run
+    #[arg(long, value_name = "DIR", default_value = "outbox")]
+    pub eml_dir: PathBuf,
 }
 
 impl Args {
@@ -18,3 +443,96 @@ impl Args {
         Self::parse()
     }
 }
+
+/// Counts that must be 1 or more.
+fn at_least_one() -> RangedU64ValueParser<usize> {
+    RangedU64ValueParser::new().range(1..)
+}
+
+fn parse_group_budget(value: &str) -> Result<(String, String), String> {
+    match value.split_once('=') {
+        Some((group, budget)) if !group.trim().is_empty() && !budget.trim().is_empty() => {
+            Ok((group.trim().to_string(), budget.trim().to_string()))
+        }
+        _ => Err(format!("expected GROUP=BUDGET, got {value:?}")),
+    }
+}
+
+fn event(
+    budget: &Option<String>,
+    currency: &Option<String>,
+    date: &Option<String>,
+    location: &Option<String>,
+    group_budgets: &[(String, String)],
+) -> Event {
+    Event {
+        budget: budget.clone(),
+        currency: currency.clone(),
+        date: date.clone(),
+        location: location.clone(),
+        group_budgets: group_budgets.iter().cloned().collect(),
+    }
+}
+
+impl ModeArgs {
+    /// The gift mode selected by the command line flags.
+    pub fn mode(&self) -> GiftMode {
+        if self.derangement {
+            GiftMode::Derangement {
+                use_groups: self.use_groups,
+                min_cycle_length: self.min_cycle_length,
+            }
+        } else {
+            GiftMode::from(self.use_groups)
+        }
+    }
+}
+
+impl GenerateArgs {
+    /// The gift mode selected by the command line flags.
+    pub fn mode(&self) -> GiftMode {
+        ModeArgs {
+            use_groups: self.use_groups,
+            derangement: self.derangement,
+            min_cycle_length: self.min_cycle_length.unwrap_or(2),
+        }
+        .mode()
+    }
+
+    /// The event details given on the command line.
+    pub fn event(&self) -> Event {
+        event(
+            &self.budget,
+            &self.currency,
+            &self.date,
+            &self.location,
+            &self.group_budget,
+        )
+    }
+}
+
+impl SendArgs {
+    /// The event details given on the command line.
+    pub fn event(&self) -> Event {
+        event(
+            &self.budget,
+            &self.currency,
+            &self.date,
+            &self.location,
+            &self.group_budget,
+        )
+    }
+}
+
+impl ValueEnum for Format {
+    fn value_variants<'a>() -> &'a [Self] {
+        &[Self::Csv, Self::Json]
+    }
+
+    fn to_possible_value(&self) -> Option<PossibleValue> {
+        Some(match self {
+            Self::Csv => PossibleValue::new("csv"),
+            Self::Json => PossibleValue::new("json"),
+        })
+    }
+}
@@ -0,0 +1,340 @@
+//! Commit-reveal draws that anyone can audit afterwards.
+//!
+//! Before the draw the organiser publishes a commitment: a SHA-256 hash of the
+//! participants, the rules and a secret only they know. Participants then contribute
+//! entropy strings, and the draw's seed is derived from the commitment, the secret and
+//! that entropy, so neither the organiser nor any one participant can steer the result.
+//! Once gifts are exchanged the organiser reveals the secret and anyone can repeat the
+//! draw with [`audit`].
+//!
+//! Participants are sorted by name before anything is hashed or drawn, so the order of
+//! rows in the participants file makes no difference.
+
+use rand::RngExt;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+use crate::error::Result;
+use crate::exclusion::Exclusion;
+use crate::gift_circle::{generate_with_seed, GenerateOptions, GiftCircleOutput};
+use crate::mode::{GiftMode, Sampling};
+use crate::people::People;
+use crate::person::{Participant, Person};
+
+const PROTOCOL: &str = "gift_circle commit-reveal v1";
+
+/// A draw made from a commitment, with the seed derived for it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CommittedDraw {
+    /// Hex SHA-256 commitment to the participants, rules and secret.
+    pub commitment: String,
+    pub output: GiftCircleOutput,
+}
+
+/// A way a published draw differs from the one recomputed by [`audit`].
+#[derive(Clone, Debug, Error, PartialEq, Eq)]
+pub enum AuditMismatch {
+    #[error(
+        "published commitment {published} does not match {recomputed}, recomputed from the \
+         participants, rules and secret"
+    )]
+    CommitmentDiffers {
+        published: String,
+        recomputed: String,
+    },
+    #[error("the draw assigns {giver} -> {recipient}, but the published assignments do not")]
+    MissingPair { giver: String, recipient: String },
+    #[error("the published assignments have {giver} -> {recipient}, which the draw does not")]
+    UnexpectedPair { giver: String, recipient: String },
+}
+
+#[derive(Serialize)]
+struct CanonicalParticipant<'a> {
+    name: &'a str,
+    group: Option<String>,
+}
+
+/// Everything that decides the draw, in a fixed order.
+#[derive(Serialize)]
+struct Statement<'a> {
+    protocol: &'static str,
+    mode: GiftMode,
+    gifts_per_person: usize,
+    sampling: Sampling,
+    participants: Vec<CanonicalParticipant<'a>>,
+    exclusions: Vec<&'a Exclusion>,
+    history: Vec<Vec<&'a Exclusion>>,
+}
+
+fn sorted_pairs<'a>(pairs: impl IntoIterator<Item = &'a Exclusion>) -> Vec<&'a Exclusion> {
+    let mut pairs: Vec<&Exclusion> = pairs.into_iter().collect();
+    pairs.sort_by(|a, b| (&a.giver, &a.recipient).cmp(&(&b.giver, &b.recipient)));
+    pairs
+}
+
+fn sha256_hex(value: &impl Serialize) -> String {
+    // Serializing plain structs, strings and numbers to JSON cannot fail.
+    let bytes = serde_json::to_vec(value).unwrap_or_default();
+    Sha256::digest(bytes)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+/// A new random secret, as 64 hex digits.
+pub fn new_secret() -> String {
+    let bytes: [u8; 32] = rand::rng().random();
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// The same people with participants sorted by name, so the draw does not depend on the
+/// order of the input file.
+pub fn canonical_people(people: &People) -> People {
+    let mut participants: Vec<Participant> = people.iter().cloned().collect();
+    participants.sort_by(|a, b| a.name.cmp(&b.name));
+    People::from(participants).with_exclusions(people.exclusions().iter().cloned())
+}
+
+/// The hex SHA-256 commitment to `people`, `options` and `secret`.
+pub fn commitment(people: &People, options: &GenerateOptions, secret: &str) -> String {
+    let people = canonical_people(people);
+    let statement = Statement {
+        protocol: PROTOCOL,
+        mode: options.mode,
+        gifts_per_person: options.gifts_per_person,
+        sampling: options.sampling,
+        participants: people
+            .iter()
+            .map(|participant| CanonicalParticipant {
+                name: &participant.name,
+                group: participant.group_label(),
+            })
+            .collect(),
+        exclusions: sorted_pairs(people.exclusions()),
+        history: options.history.years().iter().map(sorted_pairs).collect(),
+    };
+
+    #[derive(Serialize)]
+    struct Committed<'a> {
+        statement: Statement<'a>,
+        secret: &'a str,
+    }
+    sha256_hex(&Committed { statement, secret })
+}
+
+/// The seed for a committed draw. Entropy strings may be given in any order.
+pub fn derive_seed(commitment: &str, secret: &str, entropy: &[String]) -> u64 {
+    let mut entropy: Vec<&str> = entropy.iter().map(String::as_str).collect();
+    entropy.sort_unstable();
+
+    #[derive(Serialize)]
+    struct SeedInput<'a> {
+        protocol: &'static str,
+        commitment: &'a str,
+        secret: &'a str,
+        entropy: Vec<&'a str>,
+    }
+    let digest = sha256_hex(&SeedInput {
+        protocol: PROTOCOL,
+        commitment,
+        secret,
+        entropy,
+    });
+    // The first 16 hex digits of a SHA-256 digest always parse as a u64.
+    u64::from_str_radix(&digest[..16], 16).unwrap_or_default()
+}
+
+/// Draw with a seed derived from the commitment, the secret and the entropy.
+///
+/// # Errors
+///
+/// Returns [`GiftCircleError`](crate::GiftCircleError) when no circle satisfies the rules.
+pub fn generate_committed(
+    people: &People,
+    options: &GenerateOptions,
+    secret: &str,
+    entropy: &[String],
+) -> Result<CommittedDraw> {
+    let commitment = commitment(people, options, secret);
+    let seed = derive_seed(&commitment, secret, entropy);
+    let output = generate_with_seed(&canonical_people(people), options, seed)?;
+    Ok(CommittedDraw { commitment, output })
+}
+
+fn pairs(people: &[Person]) -> Vec<(&str, &str)> {
+    let mut pairs: Vec<(&str, &str)> = people
+        .iter()
+        .map(|person| (person.name.as_str(), person.assigned_person_name.as_str()))
+        .collect();
+    pairs.sort_unstable();
+    pairs
+}
+
+/// Repeat a committed draw from its revealed secret and check it against the published
+/// commitment and assignments. Returns every mismatch found, or nothing when the draw
+/// was fair.
+///
+/// # Errors
+///
+/// Returns [`GiftCircleError`](crate::GiftCircleError) when the draw cannot be repeated.
+pub fn audit(
+    people: &People,
+    options: &GenerateOptions,
+    secret: &str,
+    entropy: &[String],
+    published_commitment: &str,
+    assignments: &[Person],
+) -> Result<Vec<AuditMismatch>> {
+    let draw = generate_committed(people, options, secret, entropy)?;
+    let mut mismatches = Vec::new();
+    if !draw
+        .commitment
+        .eq_ignore_ascii_case(published_commitment.trim())
+    {
+        mismatches.push(AuditMismatch::CommitmentDiffers {
+            published: published_commitment.trim().to_string(),
+            recomputed: draw.commitment.clone(),
+        });
+    }
+
+    let expected = pairs(&draw.output.people);
+    let published = pairs(assignments);
+    for &(giver, recipient) in &expected {
+        if published.binary_search(&(giver, recipient)).is_err() {
+            mismatches.push(AuditMismatch::MissingPair {
+                giver: giver.to_string(),
+                recipient: recipient.to_string(),
+            });
+        }
+    }
+    for &(giver, recipient) in &published {
+        if expected.binary_search(&(giver, recipient)).is_err() {
+            mismatches.push(AuditMismatch::UnexpectedPair {
+                giver: giver.to_string(),
+                recipient: recipient.to_string(),
+            });
+        }
+    }
+    Ok(mismatches)
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::expect_used)]
+mod tests {
+    use super::*;
+
+    fn people() -> People {
+        People::from(vec![
+            Participant::new("Father", 1),
+            Participant::new("Mother", 1),
+            Participant::new("Son", 2),
+            Participant::new("Daughter", 2),
+            Participant::new("Aunt", 3),
+            Participant::new("Uncle", 3),
+        ])
+    }
+
+    fn reversed(people: &People) -> People {
+        let mut participants: Vec<Participant> = people.iter().cloned().collect();
+        participants.reverse();
+        People::from(participants)
+    }
+
+    fn entropy() -> Vec<String> {
+        vec!["blue".to_string(), "42".to_string()]
+    }
+
+    #[test]
+    fn input_order_makes_no_difference() {
+        let options = GenerateOptions::from(GiftMode::Grouped);
+        let forward = generate_committed(&people(), &options, "secret", &entropy()).unwrap();
+        let backward = generate_committed(
+            &reversed(&people()),
+            &options,
+            "secret",
+            &entropy().into_iter().rev().collect::<Vec<_>>(),
+        )
+        .unwrap();
+        assert_eq!(forward, backward);
+    }
+
+    #[test]
+    fn commitment_covers_participants_rules_and_secret() {
+        let options = GenerateOptions::from(GiftMode::Grouped);
+        let base = commitment(&people(), &options, "secret");
+        assert_eq!(base.len(), 64);
+        assert_ne!(base, commitment(&people(), &options, "other secret"));
+        assert_ne!(
+            base,
+            commitment(&people(), &GenerateOptions::from(GiftMode::Plain), "secret")
+        );
+        assert_ne!(
+            base,
+            commitment(
+                &people(),
+                &GenerateOptions {
+                    sampling: Sampling::Uniform,
+                    ..options.clone()
+                },
+                "secret"
+            )
+        );
+        assert_ne!(
+            base,
+            commitment(
+                &people().with_exclusions([Exclusion::new("Son", "Aunt")]),
+                &options,
+                "secret"
+            )
+        );
+    }
+
+    #[test]
+    fn audit_accepts_the_draw_and_reports_tampering() {
+        let options = GenerateOptions::from(GiftMode::Grouped);
+        let draw = generate_committed(&people(), &options, "secret", &entropy()).unwrap();
+        let assignments = draw.output.people.clone();
+        assert_eq!(
+            audit(
+                &reversed(&people()),
+                &options,
+                "secret",
+                &entropy(),
+                &draw.commitment.to_uppercase(),
+                &assignments,
+            )
+            .unwrap(),
+            Vec::new()
+        );
+
+        let mut tampered = assignments.clone();
+        tampered[0]
+            .assigned_person_name
+            .clone_from(&assignments[1].assigned_person_name);
+        let mismatches = audit(
+            &people(),
+            &options,
+            "secret",
+            &entropy(),
+            &draw.commitment,
+            &tampered,
+        )
+        .unwrap();
+        assert_eq!(mismatches.len(), 2);
+
+        let mismatches = audit(
+            &people(),
+            &options,
+            "guess",
+            &entropy(),
+            &draw.commitment,
+            &assignments,
+        )
+        .unwrap();
+        assert!(matches!(
+            mismatches[0],
+            AuditMismatch::CommitmentDiffers { .. }
+        ));
+    }
+}
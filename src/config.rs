@@ -0,0 +1,353 @@
+//! Settings for a recurring exchange, read from a `gift_circle.toml` file. Command line
+//! flags override anything set here.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+
+use crate::args::{GenerateArgs, SendArgs};
+use crate::email::SmtpSecurity;
+use crate::event::Event;
+use crate::format::Format;
+use crate::mode::Sampling;
+
+/// File name looked for in the current directory when `--config` is not given.
+pub const CONFIG_FILE_NAME: &str = "gift_circle.toml";
+
+/// How the seed for each draw is chosen.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum SeedPolicy {
+    /// Always draw with this seed.
+    Fixed(u64),
+    /// Pick a new seed for every draw and print it.
+    Named(NamedSeedPolicy),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NamedSeedPolicy {
+    Random,
+}
+
+/// Settings for emailing assignments with `send`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EmailConfig {
+    pub from: Option<String>,
+    pub subject: Option<String>,
+    pub body_template: Option<PathBuf>,
+    pub smtp_host: Option<String>,
+    pub smtp_port: Option<u16>,
+    pub smtp_security: Option<SmtpSecurity>,
+    pub smtp_username: Option<String>,
+}
+
+/// Contents of a `gift_circle.toml` file. Relative paths are relative to the file.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    pub input: Option<PathBuf>,
+    pub input_format: Option<Format>,
+    pub use_groups: Option<bool>,
+    pub derangement: Option<bool>,
+    pub min_cycle_length: Option<usize>,
+    pub gifts_per_person: Option<usize>,
+    pub seed: Option<SeedPolicy>,
+    pub sampling: Option<Sampling>,
+    pub exclusions: Option<PathBuf>,
+    #[serde(default)]
+    pub history: Vec<PathBuf>,
+    pub history_years: Option<usize>,
+    pub output: Option<PathBuf>,
+    pub output_format: Option<Format>,
+    pub output_dir: Option<PathBuf>,
+    pub print_all: Option<bool>,
+    pub encrypt: Option<bool>,
+    pub metadata_trailer: Option<bool>,
+    #[serde(default)]
+    pub event: Event,
+    #[serde(default)]
+    pub email: EmailConfig,
+}
+
+impl Config {
+    /// Read a config file, making its relative paths relative to the file.
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config from {}", path.display()))?;
+        let mut config: Self = toml::from_str(&text)
+            .with_context(|| format!("Failed to read config from {}", path.display()))?;
+        config.resolve_paths(path.parent().unwrap_or(Path::new("")));
+        Ok(config)
+    }
+
+    /// Load `explicit` when given, otherwise `gift_circle.toml` in the current directory
+    /// if there is one.
+    pub fn find(explicit: Option<&Path>) -> Result<Option<Self>> {
+        match explicit {
+            Some(path) => Self::load(path).map(Some),
+            None if Path::new(CONFIG_FILE_NAME).is_file() => {
+                Self::load(Path::new(CONFIG_FILE_NAME)).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn resolve_paths(&mut self, dir: &Path) {
+        let resolve = |path: &mut PathBuf| {
+            if path.is_relative() {
+                *path = dir.join(&*path);
+            }
+        };
+        for path in [
+            &mut self.input,
+            &mut self.exclusions,
+            &mut self.output,
+            &mut self.output_dir,
+            &mut self.email.body_template,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            resolve(path);
+        }
+        self.history.iter_mut().for_each(resolve);
+    }
+
+    /// Fill in every generate option not given on the command line.
+    pub fn fill_generate_args(&self, args: &mut GenerateArgs) {
+        let rules = &mut args.rules;
+        rules.input = rules.input.take().or_else(|| self.input.clone());
+        rules.input_format = rules.input_format.or(self.input_format);
+        args.output = args.output.take().or_else(|| self.output.clone());
+        args.output_dir = args.output_dir.take().or_else(|| self.output_dir.clone());
+        args.print_all = flag(args.print_all, args.no_print_all, self.print_all);
+        args.encrypt = flag(args.encrypt, args.no_encrypt, self.encrypt);
+        args.output_format = args.output_format.or(self.output_format);
+        args.use_groups = flag(args.use_groups, args.no_use_groups, self.use_groups);
+        args.derangement = flag(args.derangement, args.no_derangement, self.derangement);
+        args.min_cycle_length = args.min_cycle_length.or(self.min_cycle_length);
+        rules.gifts_per_person = rules.gifts_per_person.or(self.gifts_per_person);
+        rules.exclusions.file = rules
+            .exclusions
+            .file
+            .take()
+            .or_else(|| self.exclusions.clone());
+        if rules.history.is_empty() {
+            rules.history.clone_from(&self.history);
+        }
+        rules.history_years = rules.history_years.or(self.history_years);
+        rules.sampling = rules.sampling.or(self.sampling);
+        if let (None, Some(SeedPolicy::Fixed(seed))) = (args.seed, self.seed) {
+            args.seed = Some(seed);
+        }
+        args.metadata_trailer = flag(
+            args.metadata_trailer,
+            args.no_metadata_trailer,
+            self.metadata_trailer,
+        );
+        self.fill_event(
+            &mut args.budget,
+            &mut args.currency,
+            &mut args.date,
+            &mut args.location,
+            &mut args.group_budget,
+        );
+    }
+
+    /// Fill in every send option not given on the command line or in the environment.
+    pub fn fill_send_args(&self, args: &mut SendArgs) {
+        args.input = args.input.take().or_else(|| self.output.clone());
+        args.from = args.from.take().or_else(|| self.email.from.clone());
+        args.smtp_host = args
+            .smtp_host
+            .take()
+            .or_else(|| self.email.smtp_host.clone());
+        args.smtp_port = args.smtp_port.or(self.email.smtp_port);
+        args.smtp_security = args.smtp_security.or(self.email.smtp_security);
+        args.smtp_username = args
+            .smtp_username
+            .take()
+            .or_else(|| self.email.smtp_username.clone());
+        args.subject = args.subject.take().or_else(|| self.email.subject.clone());
+        args.body_template = args
+            .body_template
+            .take()
+            .or_else(|| self.email.body_template.clone());
+        self.fill_event(
+            &mut args.budget,
+            &mut args.currency,
+            &mut args.date,
+            &mut args.location,
+            &mut args.group_budget,
+        );
+    }
+
+    fn fill_event(
+        &self,
+        budget: &mut Option<String>,
+        currency: &mut Option<String>,
+        date: &mut Option<String>,
+        location: &mut Option<String>,
+        group_budgets: &mut Vec<(String, String)>,
+    ) {
+        *budget = budget.take().or_else(|| self.event.budget.clone());
+        *currency = currency.take().or_else(|| self.event.currency.clone());
+        *date = date.take().or_else(|| self.event.date.clone());
+        *location = location.take().or_else(|| self.event.location.clone());
+        if group_budgets.is_empty() {
+            group_budgets.extend(self.event.group_budgets.clone());
+        }
+    }
+}
+
+/// A flag set on the command line, or else in the config unless turned off with its
+/// `--no-` counterpart on the command line.
+fn flag(set: bool, unset: bool, config: Option<bool>) -> bool {
+    set || (!unset && config.unwrap_or(false))
+}
+
+/// Starter config written by `init`, with every option explained.
+fn starter_config(input: &str) -> String {
+    format!(
+        r#"# Settings for gift_circle. Command line flags override anything set here.
+# Relative paths are relative to this file.
+
+# Participants file, as CSV or JSON.
+input = "{input}"
+
+# Keep members of the same group from giving to each other.
+use_groups = false
+
+# Allow several smaller loops of givers instead of one big circle.
+# derangement = false
+# Smallest loop allowed with derangement; 3 or more prevents two people swapping gifts.
+# min_cycle_length = 3
+
+# Number of different people each participant gives a gift to.
+gifts_per_person = 1
+
+# "random" picks and prints a new seed for every draw; a number repeats the same draw.
+seed = "random"
+
+# "uniform" makes every valid assignment equally likely; "fast" is quicker for big groups.
+# sampling = "uniform"
+
+# CSV of giver,recipient pairs that must never be assigned.
+# exclusions = "exclusions.csv"
+
+# Earlier draws whose pairs should not be repeated, most recent first.
+# history = ["last-year.csv", "two-years-ago.csv"]
+# history_years = 2
+
+# Where to write the assignments; stdout when unset.
+# output = "assignments.csv"
+# output_format = "csv"
+# metadata_trailer = true
+
+# Write one sealed file per giver instead of printing everything.
+# output_dir = "sealed"
+# encrypt = false
+
+# Details of the exchange shown to every giver in output files and emails.
+[event]
+# budget = "30"
+# currency = "USD"
+# date = "December 24"
+# location = "Grandma's house"
+
+# Spending limits for givers in particular groups, replacing budget.
+# [event.group_budgets]
+# Kids = "15"
+
+[email]
+# from = "Santa <santa@example.com>"
+# subject = "Your gift circle assignment"
+# body_template = "email.txt"
+# smtp_host = "smtp.example.com"
+# smtp_port = 587
+# smtp_security = "starttls"
+# smtp_username = "santa@example.com"
+"#
+    )
+}
+
+/// Write a commented starter config next to `participants`, returning its path.
+pub fn write_starter_config(participants: &Path, force: bool) -> Result<PathBuf> {
+    let dir = participants.parent().unwrap_or(Path::new(""));
+    let path = dir.join(CONFIG_FILE_NAME);
+    if path.exists() && !force {
+        bail!(
+            "{} already exists; pass --force to replace it",
+            path.display()
+        );
+    }
+    let input = participants
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    fs::write(&path, starter_config(&input))
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(path)
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::expect_used)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn starter_config_parses() {
+        let config: Config = toml::from_str(&starter_config("people.csv")).unwrap();
+        assert_eq!(config.input, Some(PathBuf::from("people.csv")));
+        assert_eq!(
+            config.seed,
+            Some(SeedPolicy::Named(NamedSeedPolicy::Random))
+        );
+        assert_eq!(config.gifts_per_person, Some(1));
+    }
+
+    #[test]
+    fn load_resolves_paths_next_to_the_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(CONFIG_FILE_NAME);
+        fs::write(
+            &path,
+            "input = \"people.csv\"\nseed = 42\nhistory = [\"2025.csv\"]\n\n\
+             [event]\nbudget = \"30\"\n\n[event.group_budgets]\nKids = \"15\"\n\n\
+             [email]\nsmtp_security = \"tls\"\n",
+        )
+        .unwrap();
+
+        let config = Config::load(&path).unwrap();
+        assert_eq!(config.input, Some(dir.path().join("people.csv")));
+        assert_eq!(config.history, vec![dir.path().join("2025.csv")]);
+        assert_eq!(config.seed, Some(SeedPolicy::Fixed(42)));
+        assert_eq!(config.email.smtp_security, Some(SmtpSecurity::Tls));
+        assert_eq!(config.event.budget_for(Some("Kids")).as_deref(), Some("15"));
+    }
+
+    #[test]
+    fn command_line_can_turn_off_config_flags() {
+        let config = Config {
+            use_groups: Some(true),
+            print_all: Some(true),
+            ..Config::default()
+        };
+        let mut args = GenerateArgs {
+            no_use_groups: true,
+            ..GenerateArgs::default()
+        };
+        config.fill_generate_args(&mut args);
+        assert!(!args.use_groups);
+        assert!(args.print_all);
+    }
+
+    #[test]
+    fn unknown_keys_are_rejected() {
+        assert!(toml::from_str::<Config>("use_grups = true").is_err());
+    }
+}
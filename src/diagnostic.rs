@@ -0,0 +1,66 @@
+use std::fmt;
+
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::error::GroupLayoutProblem;
+
+/// Where a participant came from in the input.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct Location {
+    /// Position of the participant in the input, starting at 1.
+    pub entry: usize,
+    /// Line of the input CSV holding the participant, when read from CSV.
+    pub line: Option<u64>,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "line {line}"),
+            None => write!(f, "entry {}", self.entry),
+        }
+    }
+}
+
+fn locations(at: &[Location]) -> String {
+    let at: Vec<String> = at.iter().map(Location::to_string).collect();
+    at.join(", ")
+}
+
+/// One problem found while checking participants before a draw.
+#[derive(Clone, Debug, Error, PartialEq, Eq, Serialize)]
+#[serde(tag = "problem", rename_all = "snake_case")]
+pub enum Diagnostic {
+    #[error("line {line}: could not read participant: {message}")]
+    Unreadable { line: u64, message: String },
+    #[error("only {count} participants; at least three are needed to form a gift circle")]
+    TooFewParticipants { count: usize },
+    #[error("{at}: name is blank")]
+    BlankName { at: Location },
+    #[error("{at}: name {name:?} is only whitespace")]
+    WhitespaceName { name: String, at: Location },
+    #[error("{}: name {name:?} is used more than once", locations(.at))]
+    DuplicateName { name: String, at: Vec<Location> },
+    #[error("{at}: {name:?} has no group")]
+    MissingGroup { name: String, at: Location },
+    #[error("{at}: {name:?} has a malformed email address {email_address:?}")]
+    MalformedEmail {
+        name: String,
+        email_address: String,
+        at: Location,
+    },
+    #[error("exclusion refers to unknown participant {name:?}")]
+    UnknownExclusionName { name: String },
+    #[error("too many groups to number; the most is {max}")]
+    TooManyGroups { max: usize },
+    #[error("{0}")]
+    ImpossibleGroupLayout(GroupLayoutProblem),
+    #[error(
+        "loops of at least {min_cycle_length} people are impossible with {count} participants"
+    )]
+    ImpossibleCycleLength {
+        min_cycle_length: usize,
+        count: usize,
+    },
+}
@@ -0,0 +1,446 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::{anyhow, Context, Result};
+use clap::ValueEnum;
+use lettre::address::AddressError;
+use lettre::message::header::ContentType;
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::transport::smtp::client::{Tls, TlsParameters};
+use lettre::{Message, SmtpTransport, Transport};
+use serde::{Deserialize, Serialize};
+
+use crate::event::Event;
+use crate::person::Person;
+use crate::sealed::{self, SealedAssignment};
+
+pub const DEFAULT_SUBJECT: &str = "Your gift circle assignment";
+pub const DEFAULT_BODY: &str = "Hi {name},\n\nYou are getting a gift for {recipient}.\n\
+                                {event_details}{gift_notes}\nPlease keep it a secret!\n";
+
+/// Subject and body templates for assignment emails.
+///
+/// `{name}`, `{email_address}`, `{recipient}`, `{budget}`, `{date}` and `{location}` are
+/// replaced for each giver; givers with several recipients get them joined with " and ",
+/// and `{budget}` is the giver's group budget when there is one. `{event_details}` lists
+/// whichever of those event details are set, and `{gift_notes}` the recipients'
+/// wishlists, sizes, interests and things not to buy, one paragraph per recipient who
+/// filled any in; both are empty when there is nothing to list.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Template {
+    pub subject: String,
+    pub body: String,
+    /// Budget, date and location of the exchange.
+    pub event: Event,
+}
+
+impl Default for Template {
+    fn default() -> Self {
+        Self {
+            subject: DEFAULT_SUBJECT.to_string(),
+            body: DEFAULT_BODY.to_string(),
+            event: Event::default(),
+        }
+    }
+}
+
+impl Template {
+    /// Replace every `{key}` in one pass over `template`, so braces in the values are
+    /// left as they are.
+    fn fill(template: &str, values: &[(&str, String)]) -> String {
+        let mut text = String::with_capacity(template.len());
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            text.push_str(&rest[..start]);
+            rest = &rest[start..];
+            let value = rest.find('}').and_then(|end| {
+                let key = &rest[1..end];
+                let (_, value) = values.iter().find(|(name, _)| *name == key)?;
+                Some((value, end))
+            });
+            match value {
+                Some((value, end)) => {
+                    text.push_str(value);
+                    rest = &rest[end + 1..];
+                }
+                None => {
+                    text.push('{');
+                    rest = &rest[1..];
+                }
+            }
+        }
+        text.push_str(rest);
+        text
+    }
+
+    fn event_details(event: &Event) -> String {
+        let details = event.details();
+        if details.is_empty() {
+            return String::new();
+        }
+        let mut text = String::from("\n");
+        for (label, value) in details {
+            text.push_str(&format!("{label}: {value}\n"));
+        }
+        text
+    }
+
+    fn gift_notes(assignment: &SealedAssignment) -> String {
+        let mut notes = String::new();
+        for person in &assignment.people {
+            let recipient_notes = person.recipient_notes();
+            if recipient_notes.is_empty() {
+                continue;
+            }
+            notes.push_str(&format!("\nAbout {}:\n", person.assigned_person_name));
+            for (label, note) in recipient_notes {
+                notes.push_str(&format!("  {label}: {note}\n"));
+            }
+        }
+        notes
+    }
+
+    /// Render the subject and body for one giver.
+    pub fn render(&self, assignment: &SealedAssignment) -> (String, String) {
+        let giver = &assignment.people[0];
+        let recipients: Vec<&str> = assignment
+            .people
+            .iter()
+            .map(|person| person.assigned_person_name.as_str())
+            .collect();
+        let event = self.event.for_giver(giver.group_label().as_deref());
+        let values = [
+            ("name", giver.name.clone()),
+            (
+                "email_address",
+                giver.email_address.clone().unwrap_or_default(),
+            ),
+            ("recipient", recipients.join(" and ")),
+            ("budget", event.budget_for(None).unwrap_or_default()),
+            ("date", event.date.clone().unwrap_or_default()),
+            ("location", event.location.clone().unwrap_or_default()),
+            ("event_details", Self::event_details(&event)),
+            ("gift_notes", Self::gift_notes(assignment)),
+        ];
+        (
+            Self::fill(&self.subject, &values),
+            Self::fill(&self.body, &values),
+        )
+    }
+}
+
+/// How the connection to the SMTP server is secured.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpSecurity {
+    /// Plain connection; only suitable for a local relay or testing.
+    None,
+    /// Upgrade a plain connection with STARTTLS.
+    #[default]
+    Starttls,
+    /// Connect with TLS from the start.
+    Tls,
+}
+
+impl SmtpSecurity {
+    pub fn default_port(self) -> u16 {
+        match self {
+            Self::None => 25,
+            Self::Starttls => 587,
+            Self::Tls => 465,
+        }
+    }
+}
+
+/// Where and how to deliver assignment emails.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SmtpSettings {
+    pub host: String,
+    pub port: u16,
+    pub security: SmtpSecurity,
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+impl SmtpSettings {
+    /// Build a transport for these settings.
+    pub fn transport(&self) -> Result<SmtpTransport> {
+        let tls = || TlsParameters::new(self.host.clone());
+        let builder = SmtpTransport::builder_dangerous(&self.host)
+            .port(self.port)
+            .tls(match self.security {
+                SmtpSecurity::None => Tls::None,
+                SmtpSecurity::Starttls => Tls::Required(tls()?),
+                SmtpSecurity::Tls => Tls::Wrapper(tls()?),
+            });
+        let builder = match (&self.username, &self.password) {
+            (Some(username), Some(password)) => {
+                builder.credentials(Credentials::new(username.clone(), password.clone()))
+            }
+            _ => builder,
+        };
+        Ok(builder.build())
+    }
+}
+
+/// Outcome of delivering one giver's assignment.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeliveryStatus {
+    Sent,
+    Written,
+    Skipped,
+    Failed,
+}
+
+/// One row of the delivery report.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Delivery {
+    pub name: String,
+    pub email_address: Option<String>,
+    pub status: DeliveryStatus,
+    pub detail: String,
+}
+
+impl Delivery {
+    fn new(giver: &Person, status: DeliveryStatus, detail: impl Into<String>) -> Self {
+        Self {
+            name: giver.name.clone(),
+            email_address: giver.email_address.clone(),
+            status,
+            detail: detail.into(),
+        }
+    }
+}
+
+/// Build the email for one giver, or explain why it cannot be sent.
+fn build_message(
+    assignment: &SealedAssignment,
+    from: &Mailbox,
+    template: &Template,
+) -> std::result::Result<Message, Delivery> {
+    let giver = &assignment.people[0];
+    let address = giver
+        .email_address
+        .as_deref()
+        .map(str::trim)
+        .filter(|address| !address.is_empty())
+        .ok_or_else(|| Delivery::new(giver, DeliveryStatus::Skipped, "no email_address"))?;
+    let to = address
+        .parse()
+        .map(|address| Mailbox::new(Some(giver.name.clone()), address))
+        .map_err(|err: AddressError| {
+            Delivery::new(giver, DeliveryStatus::Failed, err.to_string())
+        })?;
+
+    let (subject, body) = template.render(assignment);
+    Message::builder()
+        .from(from.clone())
+        .to(to)
+        .subject(subject)
+        .header(ContentType::TEXT_PLAIN)
+        .body(body)
+        .map_err(|err| Delivery::new(giver, DeliveryStatus::Failed, err.to_string()))
+}
+
+/// Parse the sender address.
+pub fn parse_mailbox(address: &str) -> Result<Mailbox> {
+    address
+        .parse()
+        .map_err(|err| anyhow!("Invalid from address {address:?}: {err}"))
+}
+
+/// Email each giver their assignment, continuing past individual failures.
+pub fn send_all(
+    people: &[Person],
+    from: &Mailbox,
+    template: &Template,
+    transport: &impl Transport<Error: std::fmt::Display>,
+) -> Vec<Delivery> {
+    sealed::seal(people)
+        .iter()
+        .map(|assignment| {
+            let giver = &assignment.people[0];
+            match build_message(assignment, from, template) {
+                Ok(message) => match transport.send(&message) {
+                    Ok(_) => Delivery::new(giver, DeliveryStatus::Sent, ""),
+                    Err(err) => Delivery::new(giver, DeliveryStatus::Failed, err.to_string()),
+                },
+                Err(delivery) => delivery,
+            }
+        })
+        .collect()
+}
+
+/// Write each giver's email to `<slug>.eml` in `dir` instead of sending it.
+pub fn write_eml_files(
+    dir: &Path,
+    people: &[Person],
+    from: &Mailbox,
+    template: &Template,
+) -> Result<Vec<Delivery>> {
+    fs::create_dir_all(dir)
+        .with_context(|| format!("Failed to create directory {}", dir.display()))?;
+
+    sealed::seal(people)
+        .iter()
+        .map(|assignment| {
+            let giver = &assignment.people[0];
+            match build_message(assignment, from, template) {
+                Ok(message) => {
+                    let path = dir.join(format!("{}.eml", assignment.slug));
+                    fs::write(&path, message.formatted())
+                        .with_context(|| format!("Failed to write {}", path.display()))?;
+                    Ok(Delivery::new(
+                        giver,
+                        DeliveryStatus::Written,
+                        path.display().to_string(),
+                    ))
+                }
+                Err(delivery) => Ok(delivery),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use lettre::message::{header, Mailboxes};
+    use lettre::transport::stub::StubTransport;
+
+    use super::*;
+    use crate::person::Participant;
+
+    fn rows() -> Vec<Person> {
+        let mut joe = Participant::new_no_group("Joe Hill");
+        joe.email_address = Some("joe@example.com".to_string());
+        vec![
+            Person::from_assignment(&joe, &Participant::new_no_group("Jane Hill")),
+            Person::from_assignment(
+                &Participant::new_no_group("Jane Hill"),
+                &Participant::new_no_group("Joe Hill"),
+            ),
+        ]
+    }
+
+    #[test]
+    fn renders_template_placeholders() {
+        let template = Template {
+            subject: "Hi {name}".to_string(),
+            body: "{name} <{email_address}> gets {recipient} for {budget}".to_string(),
+            event: Event {
+                budget: Some("$20".to_string()),
+                ..Event::default()
+            },
+        };
+        let sealed = sealed::seal(&rows());
+        let (subject, body) = template.render(&sealed[0]);
+        assert_eq!(subject, "Hi Joe Hill");
+        assert_eq!(body, "Joe Hill <joe@example.com> gets Jane Hill for $20");
+    }
+
+    #[test]
+    fn placeholders_inside_values_are_left_alone() {
+        let template = Template {
+            subject: "Hi {name}".to_string(),
+            body: "{recipient} for {budget}, {unknown} {".to_string(),
+            event: Event {
+                budget: Some("{name}".to_string()),
+                ..Event::default()
+            },
+        };
+        let sealed = sealed::seal(&rows());
+        let (_, body) = template.render(&sealed[0]);
+        assert_eq!(body, "Jane Hill for {name}, {unknown} {");
+    }
+
+    #[test]
+    fn default_body_includes_only_the_recipients_notes() {
+        let joe = Participant {
+            wishlist: Some("Books".to_string()),
+            ..Participant::new_no_group("Joe Hill")
+        };
+        let jane = Participant {
+            sizes: Some("M".to_string()),
+            do_not_buy: Some("Perfume".to_string()),
+            ..Participant::new_no_group("Jane Hill")
+        };
+        let sealed = sealed::seal(&[
+            Person::from_assignment(&joe, &jane),
+            Person::from_assignment(&jane, &joe),
+        ]);
+        let (_, body) = Template::default().render(&sealed[0]);
+        assert_eq!(
+            body,
+            "Hi Joe Hill,\n\nYou are getting a gift for Jane Hill.\n\n\
+             About Jane Hill:\n  Sizes: M\n  Please do not buy: Perfume\n\n\
+             Please keep it a secret!\n"
+        );
+
+        let (_, body) = Template::default().render(&sealed::seal(&rows())[0]);
+        assert_eq!(
+            body,
+            "Hi Joe Hill,\n\nYou are getting a gift for Jane Hill.\n\nPlease keep it a secret!\n"
+        );
+    }
+
+    #[test]
+    fn default_body_lists_event_details_with_the_givers_group_budget() {
+        let kid = Participant::named_group("Son", "Kids");
+        let sealed = sealed::seal(&[Person::from_assignment(
+            &kid,
+            &Participant::new_no_group("Father"),
+        )]);
+        let template = Template {
+            event: Event {
+                budget: Some("30".to_string()),
+                currency: Some("USD".to_string()),
+                location: Some("Grandma's house".to_string()),
+                group_budgets: [("Kids".to_string(), "10".to_string())].into(),
+                ..Event::default()
+            },
+            ..Template::default()
+        };
+        let (_, body) = template.render(&sealed[0]);
+        assert_eq!(
+            body,
+            "Hi Son,\n\nYou are getting a gift for Father.\n\n\
+             Spending limit: 10 USD\nLocation: Grandma's house\n\n\
+             Please keep it a secret!\n"
+        );
+    }
+
+    #[test]
+    fn send_all_reports_each_giver() {
+        let from = parse_mailbox("Santa <santa@example.com>").unwrap();
+        let report = send_all(
+            &rows(),
+            &from,
+            &Template::default(),
+            &StubTransport::new_ok(),
+        );
+        let statuses: Vec<DeliveryStatus> = report.iter().map(|d| d.status).collect();
+        assert_eq!(
+            statuses,
+            vec![DeliveryStatus::Sent, DeliveryStatus::Skipped]
+        );
+    }
+
+    #[test]
+    fn names_with_commas_stay_one_address() {
+        let mut bill = Participant::new_no_group("Jones, Bill");
+        bill.email_address = Some("bill@example.com".to_string());
+        let sealed = sealed::seal(&[Person::from_assignment(
+            &bill,
+            &Participant::new_no_group("Jane Hill"),
+        )]);
+        let from = parse_mailbox("Santa <santa@example.com>").unwrap();
+        let message = build_message(&sealed[0], &from, &Template::default()).unwrap();
+        let to = message.headers().get::<header::To>().unwrap();
+        let to: Vec<Mailbox> = Mailboxes::from(to).into_iter().collect();
+        assert_eq!(to.len(), 1);
+        assert_eq!(to[0].name.as_deref(), Some("Jones, Bill"));
+        assert_eq!(to[0].email.to_string(), "bill@example.com");
+    }
+}
@@ -0,0 +1,143 @@
+use std::path::Path;
+use std::str::FromStr;
+
+use age::secrecy::SecretString;
+use age::{scrypt, x25519};
+use anyhow::{anyhow, Context, Result};
+
+use crate::person::Participant;
+
+/// How one giver's sealed assignment is locked.
+pub enum Lock {
+    /// A passphrase from the participant's `passphrase` column.
+    Passphrase(SecretString),
+    /// An age X25519 public key (`age1...`) from the participant's `public_key` column.
+    PublicKey(Box<x25519::Recipient>),
+}
+
+impl Lock {
+    /// Pick the lock for a participant, preferring a public key over a passphrase.
+    ///
+    /// # Errors
+    ///
+    /// Fails when the participant has neither column filled in or the key is malformed.
+    pub fn for_participant(participant: &Participant) -> Result<Self> {
+        let filled = |value: &Option<String>| {
+            value
+                .as_deref()
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+                .map(str::to_string)
+        };
+        if let Some(key) = filled(&participant.public_key) {
+            let recipient = x25519::Recipient::from_str(&key)
+                .map_err(|err| anyhow!("Invalid public_key for {}: {err}", participant.name))?;
+            return Ok(Self::PublicKey(Box::new(recipient)));
+        }
+        if let Some(passphrase) = filled(&participant.passphrase) {
+            return Ok(Self::Passphrase(SecretString::from(passphrase)));
+        }
+        Err(anyhow!(
+            "No passphrase or public_key to encrypt the assignment for {}",
+            participant.name
+        ))
+    }
+
+    /// Encrypt `plaintext` into an ASCII-armored age file.
+    pub fn encrypt(&self, plaintext: &[u8]) -> Result<String> {
+        let armored = match self {
+            Self::Passphrase(passphrase) => {
+                age::encrypt_and_armor(&scrypt::Recipient::new(passphrase.clone()), plaintext)
+            }
+            Self::PublicKey(recipient) => age::encrypt_and_armor(recipient.as_ref(), plaintext),
+        };
+        Ok(armored?)
+    }
+}
+
+/// The secret a participant uses to open their sealed assignment.
+pub enum Key {
+    Passphrase(SecretString),
+    Identity(Box<x25519::Identity>),
+}
+
+impl Key {
+    pub fn passphrase(passphrase: impl Into<String>) -> Self {
+        Self::Passphrase(SecretString::from(passphrase.into()))
+    }
+
+    /// Read the first `AGE-SECRET-KEY-` line from an age identity file.
+    pub fn from_identity_file(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read identity from {}", path.display()))?;
+        let line = contents
+            .lines()
+            .map(str::trim)
+            .find(|line| line.starts_with("AGE-SECRET-KEY-"))
+            .ok_or_else(|| anyhow!("No age secret key found in {}", path.display()))?;
+        let identity = x25519::Identity::from_str(line)
+            .map_err(|err| anyhow!("Invalid age secret key in {}: {err}", path.display()))?;
+        Ok(Self::Identity(Box::new(identity)))
+    }
+
+    /// Decrypt an armored or binary age file.
+    pub fn decrypt(&self, ciphertext: &[u8]) -> Result<Vec<u8>> {
+        let plaintext = match self {
+            Self::Passphrase(passphrase) => {
+                age::decrypt(&scrypt::Identity::new(passphrase.clone()), ciphertext)
+            }
+            Self::Identity(identity) => age::decrypt(identity.as_ref(), ciphertext),
+        };
+        plaintext.context("Failed to decrypt assignment; is the secret correct?")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use age::secrecy::ExposeSecret;
+
+    use super::*;
+
+    #[test]
+    fn public_key_round_trip() {
+        let identity = x25519::Identity::generate();
+        let participant = Participant {
+            name: "Joe Hill".to_string(),
+            public_key: Some(identity.to_public().to_string()),
+            ..Participant::default()
+        };
+        let sealed = Lock::for_participant(&participant)
+            .unwrap()
+            .encrypt(b"Jane Hill")
+            .unwrap();
+        assert!(sealed.starts_with("-----BEGIN AGE ENCRYPTED FILE-----"));
+
+        let key = Key::Identity(Box::new(
+            x25519::Identity::from_str(identity.to_string().expose_secret()).unwrap(),
+        ));
+        assert_eq!(key.decrypt(sealed.as_bytes()).unwrap(), b"Jane Hill");
+        let wrong = Key::Identity(Box::new(x25519::Identity::generate()));
+        assert!(wrong.decrypt(sealed.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn passphrase_round_trip() {
+        let participant = Participant {
+            name: "Joe Hill".to_string(),
+            passphrase: Some("correct horse".to_string()),
+            ..Participant::default()
+        };
+        let sealed = Lock::for_participant(&participant)
+            .unwrap()
+            .encrypt(b"Jane Hill")
+            .unwrap();
+        let key = Key::passphrase("correct horse");
+        assert_eq!(key.decrypt(sealed.as_bytes()).unwrap(), b"Jane Hill");
+    }
+
+    #[test]
+    fn missing_secret_is_an_error() {
+        let participant = Participant::new_no_group("Joe Hill");
+        assert!(Lock::for_participant(&participant).is_err());
+    }
+}
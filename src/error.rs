@@ -1,8 +1,11 @@
+use std::fmt;
+
+use serde::Serialize;
 use thiserror::Error;
 
 pub type Result<T> = std::result::Result<T, GiftCircleError>;
 
-#[derive(Debug, Error, PartialEq, Eq)]
+#[derive(Clone, Debug, Error, PartialEq, Eq)]
 pub enum GiftCircleError {
     #[error("You must submit at least three people in order to form a gift circle.")]
     TooFewParticipants { count: usize },
@@ -10,8 +13,105 @@ pub enum GiftCircleError {
     DuplicateNames(Vec<String>),
     #[error("When using groups each participant must have a group assigned!")]
     MissingGroup,
-    #[error("Sorry, no possible hamiltonian path with this set of groups.")]
-    ImpossibleGroupLayout,
+    #[error("Sorry, no possible gift circle with this set of groups; {0}")]
+    ImpossibleGroupLayout(GroupLayoutProblem),
+    #[error("Sorry, there are too many groups to number; the most is {max}.")]
+    TooManyGroups { max: usize },
+    #[error("Exclusion refers to unknown participant: {0}")]
+    UnknownExclusionName(String),
+    #[error("Sorry, no possible gift circle satisfies the exclusions.")]
+    ImpossibleExclusions,
+    #[error("Sorry, loops of at least {min_cycle_length} people are impossible with {count} participants.")]
+    ImpossibleCycleLength {
+        min_cycle_length: usize,
+        count: usize,
+    },
+    #[error("Sorry, {requested} gifts per person is impossible; some participants only have {max} possible recipients.")]
+    TooManyGiftsPerPerson { requested: usize, max: usize },
+    #[error("Sorry, there is no way for everyone to give {gifts_per_person} gifts without repeating a pair.")]
+    ImpossibleRounds { gifts_per_person: usize },
+    #[error("Sorry, everyone must give at least one gift.")]
+    NoGiftsPerPerson,
+    #[error("Sorry, only draws with one gift per person can be repaired, not {gifts_per_person}.")]
+    UnsupportedRepair { gifts_per_person: usize },
     #[error("Sorry, could not find gift circle in {attempts} attempts")]
     ExhaustedAttempts { attempts: u16 },
+    #[error(
+        "Sorry, {count} participants are too many to count every assignment; the most is {max}."
+    )]
+    TooManyToCount { count: usize, max: usize },
+    #[error(
+        "Sorry, {count} participants are too many for uniform sampling; the most is {max}. Use fast sampling instead."
+    )]
+    TooManyForUniform { count: usize, max: usize },
+}
+
+/// Why a set of groups cannot form a gift circle, and the smallest changes that fix it.
+///
+/// Everyone in a group must give to and receive from someone outside it, so no group
+/// can hold more than half of all participants.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct GroupLayoutProblem {
+    /// Label of the largest group.
+    pub group: String,
+    /// Number of people in the largest group.
+    pub size: usize,
+    /// Number of participants in total.
+    pub total: usize,
+    /// Most people any one group can hold.
+    pub max_group_size: usize,
+    /// How many people the group has beyond `max_group_size`.
+    pub excess: usize,
+    /// How many people from other groups would have to join to make a circle possible.
+    pub joiners_needed: usize,
+    /// Sizes of the fewest groups the largest group could be split into instead.
+    pub suggested_split: Vec<usize>,
+}
+
+impl GroupLayoutProblem {
+    pub fn new(group: impl Into<String>, size: usize, total: usize) -> Self {
+        let max_group_size = total / 2;
+        let mut suggested_split = Vec::new();
+        let mut unsplit = size;
+        while unsplit > 0 {
+            let part = unsplit.min(max_group_size.max(1));
+            suggested_split.push(part);
+            unsplit -= part;
+        }
+        Self {
+            group: group.into(),
+            size,
+            total,
+            max_group_size,
+            excess: size.saturating_sub(max_group_size),
+            joiners_needed: (size * 2).saturating_sub(total),
+            suggested_split,
+        }
+    }
+}
+
+impl fmt::Display for GroupLayoutProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "group {:?} has {} of {} participants but can hold at most {}, ",
+            self.group, self.size, self.total, self.max_group_size
+        )?;
+        write!(
+            f,
+            "because everyone in it must give to and receive from someone outside it. "
+        )?;
+        let mut split: Vec<String> = self.suggested_split.iter().map(usize::to_string).collect();
+        let last = split.pop().unwrap_or_default();
+        let split = if split.is_empty() {
+            last
+        } else {
+            format!("{} and {last}", split.join(", "))
+        };
+        write!(
+            f,
+            "Add {} more participants from other groups, or split {:?} into groups of {split}.",
+            self.joiners_needed, self.group
+        )
+    }
 }
@@ -0,0 +1,150 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+/// Details of the exchange itself, shared by every assignment in a draw.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Event {
+    /// Spending limit, e.g. "30".
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub budget: Option<String>,
+    /// Currency the spending limits are in, e.g. "USD".
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub currency: Option<String>,
+    /// When gifts are exchanged.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub date: Option<String>,
+    /// Where gifts are exchanged.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub location: Option<String>,
+    /// Spending limits for givers in particular groups, replacing `budget`, keyed by
+    /// group name or number.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub group_budgets: BTreeMap<String, String>,
+}
+
+impl Event {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Fill in anything not set here from `fallback`.
+    #[must_use]
+    pub fn or(self, fallback: Self) -> Self {
+        Self {
+            budget: self.budget.or(fallback.budget),
+            currency: self.currency.or(fallback.currency),
+            date: self.date.or(fallback.date),
+            location: self.location.or(fallback.location),
+            group_budgets: if self.group_budgets.is_empty() {
+                fallback.group_budgets
+            } else {
+                self.group_budgets
+            },
+        }
+    }
+
+    fn with_currency(&self, amount: &str) -> String {
+        match &self.currency {
+            Some(currency) => format!("{amount} {currency}"),
+            None => amount.to_string(),
+        }
+    }
+
+    /// The spending limit for a giver in `group`, with the currency.
+    pub fn budget_for(&self, group: Option<&str>) -> Option<String> {
+        group
+            .and_then(|group| self.group_budgets.get(group))
+            .or(self.budget.as_ref())
+            .map(|amount| self.with_currency(amount))
+    }
+
+    /// The event as one giver sees it: their own spending limit, without other groups'.
+    #[must_use]
+    pub fn for_giver(&self, group: Option<&str>) -> Self {
+        Self {
+            budget: group
+                .and_then(|group| self.group_budgets.get(group))
+                .or(self.budget.as_ref())
+                .cloned(),
+            group_budgets: BTreeMap::new(),
+            ..self.clone()
+        }
+    }
+
+    /// Labelled details that are set, in the order they are shown to people.
+    pub fn details(&self) -> Vec<(String, String)> {
+        let mut details = Vec::new();
+        if let Some(budget) = self.budget_for(None) {
+            details.push(("Spending limit".to_string(), budget));
+        }
+        for (group, amount) in &self.group_budgets {
+            details.push((
+                format!("Spending limit for {group}"),
+                self.with_currency(amount),
+            ));
+        }
+        if let Some(date) = &self.date {
+            details.push(("Date".to_string(), date.clone()));
+        }
+        if let Some(location) = &self.location {
+            details.push(("Location".to_string(), location.clone()));
+        }
+        details
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    fn event() -> Event {
+        Event {
+            budget: Some("30".to_string()),
+            currency: Some("USD".to_string()),
+            date: Some("December 24".to_string()),
+            group_budgets: BTreeMap::from([("Kids".to_string(), "15".to_string())]),
+            ..Event::default()
+        }
+    }
+
+    #[test]
+    fn group_budgets_replace_the_default() {
+        let event = event();
+        assert_eq!(event.budget_for(Some("Kids")).unwrap(), "15 USD");
+        assert_eq!(event.budget_for(Some("Hill family")).unwrap(), "30 USD");
+        assert_eq!(event.budget_for(None).unwrap(), "30 USD");
+
+        let kids = event.for_giver(Some("Kids"));
+        assert_eq!(kids.budget.as_deref(), Some("15"));
+        assert!(kids.group_budgets.is_empty());
+        assert_eq!(kids.date, event.date);
+    }
+
+    #[test]
+    fn details_list_what_is_set() {
+        assert_eq!(
+            event().details(),
+            vec![
+                ("Spending limit".to_string(), "30 USD".to_string()),
+                ("Spending limit for Kids".to_string(), "15 USD".to_string()),
+                ("Date".to_string(), "December 24".to_string()),
+            ]
+        );
+        assert!(Event::default().details().is_empty());
+    }
+
+    #[test]
+    fn or_keeps_what_is_set() {
+        let merged = Event {
+            budget: Some("50".to_string()),
+            ..Event::default()
+        }
+        .or(event());
+        assert_eq!(merged.budget.as_deref(), Some("50"));
+        assert_eq!(merged.currency.as_deref(), Some("USD"));
+        assert_eq!(merged.group_budgets.len(), 1);
+    }
+}
@@ -0,0 +1,21 @@
+use serde::{Deserialize, Serialize};
+
+/// A rule that a giver must never be assigned a particular recipient.
+///
+/// Exclusions are one-directional; add both directions to keep two people apart entirely.
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Deserialize, Serialize)]
+pub struct Exclusion {
+    /// Name of the participant giving the gift.
+    pub giver: String,
+    /// Name of the participant the giver must not be assigned.
+    pub recipient: String,
+}
+
+impl Exclusion {
+    pub fn new(giver: impl Into<String>, recipient: impl Into<String>) -> Self {
+        Self {
+            giver: giver.into(),
+            recipient: recipient.into(),
+        }
+    }
+}
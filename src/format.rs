@@ -0,0 +1,393 @@
+use std::fs::File;
+use std::io::{self, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::diagnostic::Diagnostic;
+use crate::event::Event;
+use crate::exclusion::Exclusion;
+use crate::gift_circle::{DrawMetadata, GiftCircleOutput};
+use crate::lookup::LookupEntry;
+use crate::person::{Participant, Person};
+use crate::simulation::SimulationReport;
+
+pub type Result<T> = std::result::Result<T, FormatError>;
+
+/// Why a file could not be read or written.
+#[derive(Debug, Error)]
+pub enum FormatError {
+    #[error("Failed to read {what} from {}", .path.display())]
+    Read {
+        what: &'static str,
+        path: PathBuf,
+        #[source]
+        source: Box<FormatError>,
+    },
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Csv(#[from] csv::Error),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+}
+
+/// Note which file, holding `what`, a read failed on.
+fn reading<T>(what: &'static str, path: &Path, result: Result<T>) -> Result<T> {
+    result.map_err(|source| FormatError::Read {
+        what,
+        path: path.to_path_buf(),
+        source: Box::new(source),
+    })
+}
+
+/// File formats for reading participants and writing assignments.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    #[default]
+    Csv,
+    Json,
+}
+
+impl Format {
+    /// Pick the format from a file extension, falling back to CSV.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some(extension) if extension.eq_ignore_ascii_case("json") => Self::Json,
+            _ => Self::Csv,
+        }
+    }
+
+    /// Use the explicit format when given, otherwise detect it from the path.
+    pub fn resolve(explicit: Option<Self>, path: Option<&Path>) -> Self {
+        explicit.unwrap_or_else(|| path.map(Self::from_path).unwrap_or_default())
+    }
+}
+
+/// Read every record, skipping `#` comment lines in CSV when `comments` is set.
+fn read_records<T: DeserializeOwned>(
+    path: &Path,
+    format: Format,
+    comments: bool,
+) -> Result<Vec<T>> {
+    match format {
+        Format::Csv => {
+            let mut rdr = csv::ReaderBuilder::new()
+                .comment(comments.then_some(b'#'))
+                .from_path(path)?;
+            Ok(rdr
+                .deserialize()
+                .collect::<std::result::Result<Vec<T>, _>>()?)
+        }
+        Format::Json => Ok(serde_json::from_reader(BufReader::new(File::open(path)?))?),
+    }
+}
+
+/// Read each CSV participant row, noting its line, keeping rows that fail to parse.
+fn read_csv_participants(path: &Path) -> Result<Vec<std::result::Result<Participant, csv::Error>>> {
+    let mut rdr = csv::Reader::from_path(path)?;
+    let headers = rdr.headers()?.clone();
+    Ok(rdr
+        .records()
+        .map(|record| {
+            let record = record?;
+            let mut participant: Participant = record.deserialize(Some(&headers))?;
+            participant.line = record.position().map(csv::Position::line);
+            Ok(participant)
+        })
+        .collect())
+}
+
+/// Read participants from a CSV file or a JSON array.
+pub fn read_participants(path: &Path, format: Format) -> Result<Vec<Participant>> {
+    let participants = match format {
+        Format::Csv => read_csv_participants(path).and_then(|rows| {
+            rows.into_iter()
+                .collect::<std::result::Result<Vec<_>, _>>()
+                .map_err(FormatError::from)
+        }),
+        Format::Json => read_records(path, format, false),
+    };
+    reading("input", path, participants)
+}
+
+/// Read participants like [`read_participants`], but report every CSV row that cannot
+/// be read instead of stopping at the first.
+pub fn read_participants_checked(
+    path: &Path,
+    format: Format,
+) -> Result<(Vec<Participant>, Vec<Diagnostic>)> {
+    if format == Format::Json {
+        return Ok((read_participants(path, format)?, Vec::new()));
+    }
+
+    let rows = reading("input", path, read_csv_participants(path))?;
+    let mut participants = Vec::new();
+    let mut diagnostics = Vec::new();
+    for row in rows {
+        match row {
+            Ok(participant) => participants.push(participant),
+            Err(err) => diagnostics.push(unreadable(&err)),
+        }
+    }
+    Ok((participants, diagnostics))
+}
+
+fn unreadable(err: &csv::Error) -> Diagnostic {
+    let line = err.position().map_or(0, csv::Position::line);
+    let message = match err.kind() {
+        csv::ErrorKind::Deserialize { err, .. } => err.to_string(),
+        _ => err.to_string(),
+    };
+    Diagnostic::Unreadable { line, message }
+}
+
+/// Read giver,recipient exclusion pairs from a CSV file or a JSON array.
+pub fn read_exclusions(path: &Path) -> Result<Vec<Exclusion>> {
+    reading(
+        "exclusions",
+        path,
+        read_records(path, Format::from_path(path), false),
+    )
+}
+
+/// Read a previous output, either CSV rows or a JSON document.
+///
+/// CSV rows carry no details of how the draw was made, so those are filled in from the
+/// rows themselves.
+pub fn read_output(path: &Path) -> Result<GiftCircleOutput> {
+    let output = match Format::from_path(path) {
+        Format::Csv => read_records(path, Format::Csv, true).map(|people: Vec<Person>| {
+            let mut givers: Vec<&str> = people.iter().map(|person| person.name.as_str()).collect();
+            givers.sort_unstable();
+            givers.dedup();
+            GiftCircleOutput {
+                attempts: 0,
+                used_groups: people
+                    .iter()
+                    .any(|person| person.group_number.is_some() || person.group.is_some()),
+                relaxed_history: Vec::new(),
+                gifts_per_person: (people.len() / givers.len().max(1)).max(1),
+                metadata: None,
+                event: None,
+                people,
+            }
+        }),
+        Format::Json => File::open(path)
+            .map_err(FormatError::from)
+            .and_then(|file| Ok(serde_json::from_reader(BufReader::new(file))?)),
+    };
+    reading("assignments", path, output)
+}
+
+/// Read assignment rows from a previous output, either CSV rows or a JSON document.
+pub fn read_assignments(path: &Path) -> Result<Vec<Person>> {
+    Ok(read_output(path)?.people)
+}
+
+fn write_records<T: Serialize>(writer: impl Write, records: &[T], format: Format) -> Result<()> {
+    match format {
+        Format::Csv => {
+            let mut wtr = csv::Writer::from_writer(writer);
+            for record in records {
+                wtr.serialize(record)?;
+            }
+            wtr.flush()?;
+        }
+        Format::Json => {
+            let mut writer = writer;
+            serde_json::to_writer_pretty(&mut writer, records)?;
+            writeln!(writer)?;
+        }
+    }
+    Ok(())
+}
+
+/// Write assignment rows as CSV or as a JSON array.
+pub fn write_people(writer: impl Write, people: &[Person], format: Format) -> Result<()> {
+    write_records(writer, people, format)
+}
+
+/// Write a public lookup table as CSV or as a JSON array.
+pub fn write_lookup_table(writer: impl Write, table: &[LookupEntry], format: Format) -> Result<()> {
+    write_records(writer, table, format)
+}
+
+/// Read a public lookup table from CSV or a JSON array.
+pub fn read_lookup_table(path: &Path) -> Result<Vec<LookupEntry>> {
+    reading(
+        "lookup table",
+        path,
+        read_records(path, Format::from_path(path), false),
+    )
+}
+
+/// Write a simulation report as a JSON document, or as three CSV tables separated by
+/// blank lines: the giver by recipient counts, the attempt counts and the group bias.
+pub fn write_simulation(
+    writer: impl Write,
+    report: &SimulationReport,
+    format: Format,
+) -> Result<()> {
+    let mut writer = writer;
+    match format {
+        Format::Csv => {
+            {
+                let mut wtr = csv::WriterBuilder::new()
+                    .flexible(true)
+                    .from_writer(&mut writer);
+                let header =
+                    std::iter::once("giver").chain(report.names.iter().map(String::as_str));
+                wtr.write_record(header)?;
+                for (name, row) in report.names.iter().zip(&report.pair_counts) {
+                    wtr.write_record(
+                        std::iter::once(name.clone()).chain(row.iter().map(usize::to_string)),
+                    )?;
+                }
+                wtr.flush()?;
+            }
+            writeln!(writer)?;
+            write_records(&mut writer, &report.attempts, format)?;
+            writeln!(writer)?;
+            write_records(&mut writer, &report.groups, format)?;
+        }
+        Format::Json => {
+            serde_json::to_writer_pretty(&mut writer, report)?;
+            writeln!(writer)?;
+        }
+    }
+    Ok(())
+}
+
+/// Write assignments as CSV rows or as a JSON document. CSV rows are followed by the
+/// event details, if any, as `#` comment lines.
+pub fn write_output(writer: impl Write, output: &GiftCircleOutput, format: Format) -> Result<()> {
+    match format {
+        Format::Csv => {
+            let mut writer = writer;
+            write_people(&mut writer, &output.people, format)?;
+            if let Some(event) = &output.event {
+                write_event_trailer(writer, event)?;
+            }
+        }
+        Format::Json => {
+            let mut writer = writer;
+            serde_json::to_writer_pretty(&mut writer, output)?;
+            writeln!(writer)?;
+        }
+    }
+    Ok(())
+}
+
+/// Append event details to CSV output as `#` comment lines, which [`read_assignments`]
+/// skips.
+pub fn write_event_trailer(mut writer: impl Write, event: &Event) -> Result<()> {
+    for (label, value) in event.details() {
+        writeln!(writer, "# {label}: {value}")?;
+    }
+    Ok(())
+}
+
+/// Append how a draw was made to CSV output as `#` comment lines, which
+/// [`read_assignments`] skips.
+pub fn write_csv_trailer(mut writer: impl Write, metadata: &DrawMetadata) -> Result<()> {
+    writeln!(writer, "# gift_circle version: {}", metadata.version)?;
+    writeln!(writer, "# seed: {}", metadata.seed)?;
+    writeln!(writer, "# mode: {}", metadata.mode)?;
+    if !metadata.sampling.is_fast() {
+        writeln!(writer, "# sampling: {}", metadata.sampling)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mode::{GiftMode, Sampling};
+
+    #[test]
+    fn detects_format_from_extension() {
+        assert_eq!(Format::from_path(Path::new("people.JSON")), Format::Json);
+        assert_eq!(Format::from_path(Path::new("people.csv")), Format::Csv);
+        assert_eq!(Format::from_path(Path::new("people")), Format::Csv);
+        assert_eq!(
+            Format::resolve(Some(Format::Csv), Some(Path::new("people.json"))),
+            Format::Csv
+        );
+    }
+
+    #[test]
+    fn json_output_round_trips() {
+        let output = GiftCircleOutput {
+            people: vec![Person::from_assignment(
+                &Participant::new("Father", 1),
+                &Participant {
+                    wishlist: Some("Lego".to_string()),
+                    ..Participant::new("Son", 2)
+                },
+            )],
+            attempts: 2,
+            used_groups: true,
+            relaxed_history: vec![Exclusion::new("Father", "Mother")],
+            gifts_per_person: 1,
+            metadata: Some(DrawMetadata {
+                version: "1.0.0".to_string(),
+                seed: 7,
+                mode: GiftMode::Derangement {
+                    use_groups: true,
+                    min_cycle_length: 3,
+                },
+                sampling: Sampling::Uniform,
+            }),
+            event: Some(Event {
+                budget: Some("30".to_string()),
+                date: Some("December 24".to_string()),
+                ..Event::default()
+            }),
+        };
+        let mut buffer = Vec::new();
+        write_output(&mut buffer, &output, Format::Json).unwrap();
+        let parsed: GiftCircleOutput = serde_json::from_slice(&buffer).unwrap();
+        assert_eq!(parsed, output);
+    }
+
+    #[test]
+    fn csv_output_carries_recipient_notes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("assignments.csv");
+        let son = Participant {
+            sizes: Some("Kids M".to_string()),
+            do_not_buy: Some("Candy".to_string()),
+            ..Participant::new("Son", 2)
+        };
+        let people = vec![Person::from_assignment(
+            &Participant::new("Father", 1),
+            &son,
+        )];
+        write_people(File::create(&path).unwrap(), &people, Format::Csv).unwrap();
+
+        let text = std::fs::read_to_string(&path).unwrap();
+        assert!(text.starts_with(
+            "name,email_address,group_number,assigned_person_name,group,\
+             recipient_wishlist,recipient_sizes,recipient_interests,recipient_do_not_buy\n"
+        ));
+        assert_eq!(read_assignments(&path).unwrap(), people);
+    }
+
+    #[test]
+    fn read_errors_name_the_file() {
+        let err = read_exclusions(Path::new("missing.csv")).unwrap_err();
+        assert!(matches!(
+            &err,
+            FormatError::Read { what: "exclusions", path, source }
+                if path == Path::new("missing.csv") && matches!(**source, FormatError::Csv(_))
+        ));
+        assert_eq!(
+            err.to_string(),
+            "Failed to read exclusions from missing.csv"
+        );
+    }
+}
@@ -1,12 +1,22 @@
+use std::collections::HashSet;
+
 use rand::prelude::{Rng, RngExt, SliceRandom};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use serde::{Deserialize, Serialize};
 
 use crate::error::{GiftCircleError, Result};
-use crate::mode::GiftMode;
+use crate::event::Event;
+use crate::exclusion::Exclusion;
+use crate::history::History;
+use crate::mode::{GiftMode, Sampling};
 use crate::people::{GroupedPeople, People};
 use crate::person::Person;
+use crate::solver;
+use crate::uniform::{self, CircleCounts, LoopCounts};
 
 /// A successfully generated gift circle and metadata about how it was built.
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct GiftCircleOutput {
     /// Participants in gift order with assigned recipients populated.
     pub people: Vec<Person>,
@@ -14,14 +24,172 @@ pub struct GiftCircleOutput {
     pub attempts: u16,
     /// Whether group constraints were applied.
     pub used_groups: bool,
+    /// History pairs that could not be avoided and were repeated in this draw.
+    pub relaxed_history: Vec<Exclusion>,
+    /// Number of recipients each participant gives to; `people` holds one row per pair,
+    /// one complete round of assignments after another.
+    pub gifts_per_person: usize,
+    /// How the draw was made, when it was made by [`generate_with_seed`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<DrawMetadata>,
+    /// Budget, date and location of the exchange, when the organiser gave any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub event: Option<Event>,
+}
+
+/// What is needed to reproduce a draw exactly from the same input.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DrawMetadata {
+    /// Version of gift_circle that made the draw; other versions may draw differently.
+    pub version: String,
+    /// Seed for the random number generator.
+    pub seed: u64,
+    /// Gift mode used for the draw.
+    pub mode: GiftMode,
+    /// How the draw picked among valid assignments.
+    #[serde(default, skip_serializing_if = "Sampling::is_fast")]
+    pub sampling: Sampling,
+}
+
+/// Settings for [`generate_with_options`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GenerateOptions {
+    /// Whether gift assignments must respect family group boundaries.
+    pub mode: GiftMode,
+    /// Previous draws whose pairs should not be repeated.
+    pub history: History,
+    /// How many different people each participant gives to (and receives from).
+    pub gifts_per_person: usize,
+    /// How to pick among all the assignments that satisfy the rules.
+    pub sampling: Sampling,
 }
 
-enum ValidatedPeople {
+impl Default for GenerateOptions {
+    fn default() -> Self {
+        Self {
+            mode: GiftMode::default(),
+            history: History::default(),
+            gifts_per_person: 1,
+            sampling: Sampling::default(),
+        }
+    }
+}
+
+impl From<GiftMode> for GenerateOptions {
+    fn from(mode: GiftMode) -> Self {
+        Self {
+            mode,
+            ..Self::default()
+        }
+    }
+}
+
+pub(crate) enum ValidatedPeople {
     Plain,
     Grouped(GroupedPeople),
 }
 
-fn validate_people(from_people: &People, mode: GiftMode) -> Result<ValidatedPeople> {
+/// Which giver/recipient pairs are allowed, resolved to participant indices.
+#[derive(Clone)]
+pub(crate) struct Rules {
+    groups: Option<Vec<u16>>,
+    excluded: HashSet<(usize, usize)>,
+    /// Reported when no assignment exists and nothing beyond the groups was excluded.
+    layout_error: GiftCircleError,
+}
+
+impl Rules {
+    pub(crate) fn new(people: &People, validated: &ValidatedPeople) -> Result<Self> {
+        let (groups, layout_error) = match validated {
+            ValidatedPeople::Plain => (None, GiftCircleError::ImpossibleExclusions),
+            ValidatedPeople::Grouped(grouped) => (
+                Some(
+                    (0..grouped.len())
+                        .map(|index| grouped.group_at(index))
+                        .collect(),
+                ),
+                grouped.impossible_layout_error(),
+            ),
+        };
+        Ok(Self {
+            groups,
+            excluded: people.excluded_index_pairs()?,
+            layout_error,
+        })
+    }
+
+    /// Also forbid the given history pairs, ignoring anyone no longer participating.
+    fn with_history<'a>(
+        &self,
+        people: &People,
+        pairs: impl IntoIterator<Item = &'a Exclusion>,
+    ) -> Self {
+        let index_of = |name: &str| {
+            people
+                .iter()
+                .position(|participant| participant.name == name)
+        };
+        self.excluding(
+            pairs
+                .into_iter()
+                .filter_map(|pair| Some((index_of(&pair.giver)?, index_of(&pair.recipient)?))),
+        )
+    }
+
+    /// Also forbid the given `(giver, recipient)` index pairs.
+    fn excluding(&self, pairs: impl IntoIterator<Item = (usize, usize)>) -> Self {
+        let mut rules = self.clone();
+        rules.excluded.extend(pairs);
+        rules
+    }
+
+    /// The most gifts everyone can give and receive, counting allowed pairs per person.
+    fn max_gifts_per_person(&self, len: usize) -> usize {
+        (0..len)
+            .map(|person| {
+                let gives = (0..len).filter(|&other| self.allows(person, other)).count();
+                let receives = (0..len).filter(|&other| self.allows(other, person)).count();
+                gives.min(receives)
+            })
+            .min()
+            .unwrap_or(0)
+    }
+
+    pub(crate) fn allows(&self, giver: usize, recipient: usize) -> bool {
+        giver != recipient
+            && self
+                .groups
+                .as_ref()
+                .is_none_or(|groups| groups[giver] != groups[recipient])
+            && !self.excluded.contains(&(giver, recipient))
+    }
+
+    fn is_valid_circle(&self, path: &[usize]) -> bool {
+        path.iter()
+            .enumerate()
+            .all(|(position, &giver)| self.allows(giver, path[(position + 1) % path.len()]))
+    }
+
+    fn is_valid_derangement(&self, recipients: &[usize], min_cycle_length: usize) -> bool {
+        recipients
+            .iter()
+            .enumerate()
+            .all(|(giver, &recipient)| self.allows(giver, recipient))
+            && cycles_from_recipients(recipients)
+                .iter()
+                .all(|cycle| cycle.len() >= min_cycle_length)
+    }
+
+    pub(crate) fn impossible_error(&self) -> GiftCircleError {
+        if self.excluded.is_empty() {
+            self.layout_error.clone()
+        } else {
+            GiftCircleError::ImpossibleExclusions
+        }
+    }
+}
+
+pub(crate) fn validate_people(from_people: &People, mode: GiftMode) -> Result<ValidatedPeople> {
     if from_people.len() <= 2 {
         return Err(GiftCircleError::TooFewParticipants {
             count: from_people.len(),
@@ -33,41 +201,49 @@ fn validate_people(from_people: &People, mode: GiftMode) -> Result<ValidatedPeop
         return Err(GiftCircleError::DuplicateNames(duplicates));
     }
 
-    match mode {
-        GiftMode::Plain => Ok(ValidatedPeople::Plain),
-        GiftMode::Grouped => GroupedPeople::try_from(from_people).map(ValidatedPeople::Grouped),
+    if let Some(min_cycle_length) = mode.min_cycle_length() {
+        if min_cycle_length > from_people.len() {
+            return Err(GiftCircleError::ImpossibleCycleLength {
+                min_cycle_length,
+                count: from_people.len(),
+            });
+        }
+    }
+
+    if mode.uses_groups() {
+        GroupedPeople::try_from(from_people).map(ValidatedPeople::Grouped)
+    } else {
+        Ok(ValidatedPeople::Plain)
     }
 }
 
 fn generate_group_path_indices(grouped: &GroupedPeople, rng: &mut impl Rng) -> Vec<usize> {
-    let people: &People = grouped;
-    let mut remaining: Vec<usize> = (0..people.len()).collect();
-    let mut path_indices = Vec::with_capacity(people.len());
+    let mut remaining: Vec<usize> = (0..grouped.len()).collect();
+    let mut path_indices = Vec::with_capacity(grouped.len());
     let mut previous_group: u16 = 0;
 
     while !remaining.is_empty() {
         let largest_np_group = grouped.largest_non_prev_group(previous_group, &remaining);
 
-        let candidate_indices: Vec<usize> = if (largest_np_group.size as usize * 2)
-            > remaining.len()
-        {
-            remaining
-                .iter()
-                .copied()
-                .filter(|&index| GroupedPeople::group_at(people, index) == largest_np_group.number)
-                .collect()
-        } else {
-            remaining
-                .iter()
-                .copied()
-                .filter(|&index| GroupedPeople::group_at(people, index) != previous_group)
-                .collect()
-        };
+        let candidate_indices: Vec<usize> =
+            if (largest_np_group.size as usize * 2) > remaining.len() {
+                remaining
+                    .iter()
+                    .copied()
+                    .filter(|&index| grouped.group_at(index) == largest_np_group.number)
+                    .collect()
+            } else {
+                remaining
+                    .iter()
+                    .copied()
+                    .filter(|&index| grouped.group_at(index) != previous_group)
+                    .collect()
+            };
 
         let choice_pos = rng.random_range(0..candidate_indices.len());
         let choice_index = candidate_indices[choice_pos];
 
-        previous_group = GroupedPeople::group_at(people, choice_index);
+        previous_group = grouped.group_at(choice_index);
         if let Some(pos) = remaining.iter().position(|&index| index == choice_index) {
             remaining.swap_remove(pos);
         }
@@ -77,6 +253,231 @@ fn generate_group_path_indices(grouped: &GroupedPeople, rng: &mut impl Rng) -> V
     path_indices
 }
 
+/// Random draws to try before falling back to an exhaustive search.
+const FAST_ATTEMPTS: u16 = 10;
+
+/// Try the fast random walks a few times, then fall back to an exhaustive randomised
+/// search so a circle is always found when one exists.
+fn find_path_indices(
+    people: &People,
+    validated: &ValidatedPeople,
+    rules: &Rules,
+    rng: &mut impl Rng,
+) -> Result<(Vec<usize>, u16)> {
+    for attempt in 1..=FAST_ATTEMPTS {
+        let candidate_path = match validated {
+            ValidatedPeople::Plain => generate_no_group_path_indices(people, rng),
+            ValidatedPeople::Grouped(grouped) => generate_group_path_indices(grouped, rng),
+        };
+        if rules.is_valid_circle(&candidate_path) {
+            return Ok((candidate_path, attempt));
+        }
+    }
+
+    solver::find_circle(
+        people.len(),
+        rules.groups.as_deref(),
+        |giver, recipient| rules.allows(giver, recipient),
+        rng,
+    )
+    .map(|path| (path, FAST_ATTEMPTS + 1))
+    .ok_or_else(|| rules.impossible_error())
+}
+
+/// Pick a circle uniformly among all valid ones by counting every circle, which is only
+/// possible for up to 16 people.
+fn sample_path_indices(
+    people: &People,
+    rules: &Rules,
+    rng: &mut impl Rng,
+) -> Result<(Vec<usize>, u16)> {
+    let counts = CircleCounts::new(people.len(), |giver, recipient| {
+        rules.allows(giver, recipient)
+    })
+    .ok_or(GiftCircleError::TooManyForUniform {
+        count: people.len(),
+        max: uniform::MAX_EXACT_LEN,
+    })?;
+    let mut path = counts.sample(rng).ok_or_else(|| rules.impossible_error())?;
+    // Start the listing at a random giver so the output order gives nothing away.
+    let start = rng.random_range(0..path.len());
+    path.rotate_left(start);
+    Ok((path, 1))
+}
+
+/// Split each giver's recipient into loops, each listed in gift order.
+pub(crate) fn cycles_from_recipients(recipients: &[usize]) -> Vec<Vec<usize>> {
+    let mut seen = vec![false; recipients.len()];
+    let mut cycles = Vec::new();
+    for start in 0..recipients.len() {
+        let mut cycle = Vec::new();
+        let mut index = start;
+        while !seen[index] {
+            seen[index] = true;
+            cycle.push(index);
+            index = recipients[index];
+        }
+        if !cycle.is_empty() {
+            cycles.push(cycle);
+        }
+    }
+    cycles
+}
+
+/// Try random permutations a few times, then fall back to an exhaustive randomised
+/// search so assignments are always found when they exist.
+fn find_derangement_cycles(
+    people: &People,
+    rules: &Rules,
+    min_cycle_length: usize,
+    rng: &mut impl Rng,
+) -> Result<(Vec<Vec<usize>>, u16)> {
+    for attempt in 1..=FAST_ATTEMPTS {
+        let recipients = generate_no_group_path_indices(people, rng);
+        if rules.is_valid_derangement(&recipients, min_cycle_length) {
+            return Ok((cycles_from_recipients(&recipients), attempt));
+        }
+    }
+
+    solver::find_derangement(
+        people.len(),
+        min_cycle_length,
+        |giver, recipient| rules.allows(giver, recipient),
+        rng,
+    )
+    .map(|recipients| (cycles_from_recipients(&recipients), FAST_ATTEMPTS + 1))
+    .ok_or_else(|| rules.impossible_error())
+}
+
+/// Pick assignments uniformly among all valid ones by counting every way to split
+/// everyone into loops, which is only possible for up to 16 people.
+fn sample_derangement_cycles(
+    people: &People,
+    rules: &Rules,
+    min_cycle_length: usize,
+    rng: &mut impl Rng,
+) -> Result<(Vec<Vec<usize>>, u16)> {
+    let counts = LoopCounts::new(people.len(), min_cycle_length, |giver, recipient| {
+        rules.allows(giver, recipient)
+    })
+    .ok_or(GiftCircleError::TooManyForUniform {
+        count: people.len(),
+        max: uniform::MAX_EXACT_LEN,
+    })?;
+    let cycles = counts.sample(rng).ok_or_else(|| rules.impossible_error())?;
+    Ok((cycles, 1))
+}
+
+pub(crate) fn find_cycles(
+    people: &People,
+    validated: &ValidatedPeople,
+    rules: &Rules,
+    options: &GenerateOptions,
+    rng: &mut impl Rng,
+) -> Result<(Vec<Vec<usize>>, u16)> {
+    match (options.mode.min_cycle_length(), options.sampling) {
+        (Some(min_cycle_length), Sampling::Fast) => {
+            find_derangement_cycles(people, rules, min_cycle_length, rng)
+        }
+        (Some(min_cycle_length), Sampling::Uniform) => {
+            sample_derangement_cycles(people, rules, min_cycle_length, rng)
+        }
+        (None, Sampling::Fast) => find_path_indices(people, validated, rules, rng)
+            .map(|(path, attempts)| (vec![path], attempts)),
+        (None, Sampling::Uniform) => {
+            sample_path_indices(people, rules, rng).map(|(path, attempts)| (vec![path], attempts))
+        }
+    }
+}
+
+/// Giver/recipient index pairs used by a set of loops.
+fn pairs_from_cycles(cycles: &[Vec<usize>]) -> impl Iterator<Item = (usize, usize)> + '_ {
+    cycles.iter().flat_map(|cycle| {
+        cycle
+            .iter()
+            .enumerate()
+            .map(|(position, &giver)| (giver, cycle[(position + 1) % cycle.len()]))
+    })
+}
+
+/// Draw `rounds` sets of loops that share no giver/recipient pair, starting over when an
+/// earlier round leaves a later one with no valid assignments, and searching every
+/// round together when starting over keeps failing.
+fn find_rounds(
+    people: &People,
+    validated: &ValidatedPeople,
+    rules: &Rules,
+    options: &GenerateOptions,
+    rounds: usize,
+    rng: &mut impl Rng,
+) -> Result<(Vec<Vec<usize>>, u16)> {
+    const MAX_RESTARTS: u16 = 500;
+
+    let mut attempts: u16 = 0;
+    for _ in 0..MAX_RESTARTS {
+        let mut cycles: Vec<Vec<usize>> = Vec::new();
+        for round in 0..rounds {
+            let round_rules = rules.excluding(pairs_from_cycles(&cycles));
+            match find_cycles(people, validated, &round_rules, options, rng) {
+                Ok((round_cycles, round_attempts)) => {
+                    attempts = attempts.saturating_add(round_attempts);
+                    cycles.extend(round_cycles);
+                }
+                Err(GiftCircleError::ImpossibleExclusions) if round > 0 => break,
+                Err(err) => return Err(err),
+            }
+            if round + 1 == rounds {
+                return Ok((cycles, attempts));
+            }
+        }
+    }
+
+    search_rounds(people, rules, options.mode.min_cycle_length(), rounds, None)
+        .map(|cycles| (cycles, attempts.saturating_add(1)))
+        .ok_or(GiftCircleError::ImpossibleRounds {
+            gifts_per_person: rounds,
+        })
+}
+
+/// The first `rounds` sets of loops sharing no giver/recipient pair, searching every
+/// combination of rounds depth first, or `None` when there is none. Some layouts, such
+/// as everyone giving to everyone else among four or six people, never split into
+/// separate circles however the rounds are drawn.
+///
+/// Rounds are searched in order of whom person 0 gives to, after `after`, since the
+/// same rounds in another order are no different.
+fn search_rounds(
+    people: &People,
+    rules: &Rules,
+    min_cycle_length: Option<usize>,
+    rounds: usize,
+    after: Option<usize>,
+) -> Option<Vec<Vec<usize>>> {
+    if rounds == 0 {
+        return Some(Vec::new());
+    }
+    if rules.max_gifts_per_person(people.len()) < rounds {
+        return None;
+    }
+    let mut circles = Circles::new(people, rules.clone(), min_cycle_length);
+    while let Some(mut cycles) = circles.next_cycles() {
+        let first_recipient = pairs_from_cycles(&cycles)
+            .find(|&(giver, _)| giver == 0)
+            .map(|(_, recipient)| recipient);
+        if first_recipient <= after {
+            continue;
+        }
+        let rest = rules.excluding(pairs_from_cycles(&cycles));
+        if let Some(more) =
+            search_rounds(people, &rest, min_cycle_length, rounds - 1, first_recipient)
+        {
+            cycles.extend(more);
+            return Some(cycles);
+        }
+    }
+    None
+}
+
 fn generate_no_group_path_indices(people: &People, rng: &mut impl Rng) -> Vec<usize> {
     let mut path_indices: Vec<usize> = (0..people.len()).collect();
     path_indices.shuffle(rng);
@@ -87,7 +488,8 @@ fn generate_no_group_path_indices(people: &People, rng: &mut impl Rng) -> Vec<us
 ///
 /// # Errors
 ///
-/// Returns [`GiftCircleError`] when validation fails or no valid group circle is found.
+/// Returns [`GiftCircleError`] when validation fails or no circle satisfies the groups
+/// and exclusions.
 pub fn generate(from_people: &People, mode: GiftMode) -> Result<GiftCircleOutput> {
     generate_with_rng(from_people, mode, &mut rand::rng())
 }
@@ -96,48 +498,320 @@ pub fn generate(from_people: &People, mode: GiftMode) -> Result<GiftCircleOutput
 ///
 /// # Errors
 ///
-/// Returns [`GiftCircleError`] when validation fails or no valid group circle is found.
+/// Returns [`GiftCircleError`] when validation fails or no circle satisfies the groups
+/// and exclusions.
 pub fn generate_with_rng(
     from_people: &People,
     mode: GiftMode,
     rng: &mut impl Rng,
 ) -> Result<GiftCircleOutput> {
-    match validate_people(from_people, mode)? {
-        ValidatedPeople::Plain => {
-            let path = generate_no_group_path_indices(from_people, rng);
-            Ok(GiftCircleOutput {
-                people: from_people.assign_from_path(&path),
-                attempts: 1,
-                used_groups: mode.uses_groups(),
+    generate_with_options(from_people, &GenerateOptions::from(mode), rng)
+}
+
+/// Generate a gift circle using the provided options and random number generator.
+///
+/// History pairs are avoided where possible. When they make a circle impossible the
+/// oldest years are relaxed first, and any pairs that end up repeated are reported in
+/// [`GiftCircleOutput::relaxed_history`].
+///
+/// # Errors
+///
+/// Returns [`GiftCircleError`] when validation fails or no circle satisfies the groups
+/// and exclusions.
+pub fn generate_with_options(
+    from_people: &People,
+    options: &GenerateOptions,
+    rng: &mut impl Rng,
+) -> Result<GiftCircleOutput> {
+    let validated = validate_people(from_people, options.mode)?;
+    let rules = Rules::new(from_people, &validated)?;
+    let years = options.history.years();
+
+    let gifts_per_person = options.gifts_per_person;
+    if gifts_per_person == 0 {
+        return Err(GiftCircleError::NoGiftsPerPerson);
+    }
+    let max_gifts_per_person = rules.max_gifts_per_person(from_people.len());
+    if gifts_per_person > max_gifts_per_person.max(1) {
+        return Err(GiftCircleError::TooManyGiftsPerPerson {
+            requested: gifts_per_person,
+            max: max_gifts_per_person,
+        });
+    }
+
+    let mut kept_years = years.len();
+    let (cycles, attempts) = loop {
+        let history_rules = rules.with_history(from_people, years[..kept_years].iter().flatten());
+        match find_rounds(
+            from_people,
+            &validated,
+            &history_rules,
+            options,
+            gifts_per_person,
+            rng,
+        ) {
+            Err(
+                GiftCircleError::ImpossibleExclusions | GiftCircleError::ImpossibleRounds { .. },
+            ) if kept_years > 0 => kept_years -= 1,
+            found => break found?,
+        }
+    };
+
+    let people = from_people.assign_from_cycles(&cycles);
+    let relaxed_history = years[kept_years..]
+        .iter()
+        .flatten()
+        .filter(|pair| {
+            people.iter().any(|person| {
+                person.name == pair.giver && person.assigned_person_name == pair.recipient
             })
+        })
+        .cloned()
+        .collect();
+
+    Ok(GiftCircleOutput {
+        people,
+        attempts,
+        used_groups: options.mode.uses_groups(),
+        relaxed_history,
+        gifts_per_person,
+        metadata: None,
+        event: None,
+    })
+}
+
+/// Generate a gift circle from a seed, recording the seed in
+/// [`GiftCircleOutput::metadata`] so the same draw can be made again.
+///
+/// # Errors
+///
+/// Returns [`GiftCircleError`] when validation fails or no circle satisfies the groups
+/// and exclusions.
+pub fn generate_with_seed(
+    from_people: &People,
+    options: &GenerateOptions,
+    seed: u64,
+) -> Result<GiftCircleOutput> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut output = generate_with_options(from_people, options, &mut rng)?;
+    output.metadata = Some(DrawMetadata {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        seed,
+        mode: options.mode,
+        sampling: options.sampling,
+    });
+    Ok(output)
+}
+
+/// Count every set of assignments `mode` allows: circles, or ways to split everyone into
+/// loops with [`GiftMode::Derangement`]. Each circle is counted once, whoever it starts
+/// with.
+///
+/// # Errors
+///
+/// Returns [`GiftCircleError`] when validation fails, or
+/// [`GiftCircleError::TooManyToCount`] for more than 16 participants; see
+/// [`estimate_circles`] for those.
+pub fn count_circles(people: &People, mode: GiftMode) -> Result<u64> {
+    let validated = validate_people(people, mode)?;
+    let rules = Rules::new(people, &validated)?;
+    let allowed = |giver, recipient| rules.allows(giver, recipient);
+    match mode.min_cycle_length() {
+        None => CircleCounts::new(people.len(), allowed).map(|counts| counts.total()),
+        Some(min_cycle_length) => {
+            LoopCounts::new(people.len(), min_cycle_length, allowed).map(|counts| counts.total())
         }
-        ValidatedPeople::Grouped(grouped) => {
-            const MAX_ATTEMPTS: u16 = 500;
-            let mut attempt_count: u16 = 0;
-            let mut path = Vec::new();
-
-            while attempt_count < MAX_ATTEMPTS {
-                attempt_count += 1;
-                let candidate_path = generate_group_path_indices(&grouped, rng);
-                if grouped.is_valid_gift_circle(&candidate_path) {
-                    path = candidate_path;
-                    break;
-                }
+    }
+    .ok_or(GiftCircleError::TooManyToCount {
+        count: people.len(),
+        max: uniform::MAX_EXACT_LEN,
+    })
+}
+
+/// An estimate from [`estimate_circles`], with what it was based on.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CircleEstimate {
+    /// Estimated number of sets of assignments.
+    pub circles: f64,
+    /// Random orders that satisfied the rules; the fewer, the rougher the estimate.
+    pub fitting_orders: usize,
+    /// Random orders checked.
+    pub samples: usize,
+}
+
+/// Estimate how many sets of assignments `mode` allows from the share of `samples`
+/// random orders that satisfy the rules. Rules that allow only a tiny share of orders
+/// may estimate zero.
+///
+/// # Errors
+///
+/// Returns [`GiftCircleError`] when validation fails.
+pub fn estimate_circles(
+    people: &People,
+    mode: GiftMode,
+    samples: usize,
+    rng: &mut impl Rng,
+) -> Result<CircleEstimate> {
+    let validated = validate_people(people, mode)?;
+    let rules = Rules::new(people, &validated)?;
+    let fitting_orders = (0..samples)
+        .filter(|_| {
+            let order = generate_no_group_path_indices(people, rng);
+            match mode.min_cycle_length() {
+                None => rules.is_valid_circle(&order),
+                Some(min_cycle_length) => rules.is_valid_derangement(&order, min_cycle_length),
             }
+        })
+        .count();
 
-            if path.is_empty() {
-                return Err(GiftCircleError::ExhaustedAttempts {
-                    attempts: MAX_ATTEMPTS,
-                });
+    let orders: f64 = (1..=people.len()).map(|count| count as f64).product();
+    // Each circle can be listed starting from any of its people.
+    let per_assignment = match mode.min_cycle_length() {
+        None => people.len() as f64,
+        Some(_) => 1.0,
+    };
+    Ok(CircleEstimate {
+        circles: orders / per_assignment * fitting_orders as f64 / samples.max(1) as f64,
+        fitting_orders,
+        samples,
+    })
+}
+
+/// Every set of assignments `mode` allows, each listed once, in a fixed order.
+///
+/// Each item holds one row per giver, like [`GiftCircleOutput::people`]. There can be a
+/// great many; [`count_circles`] says how many without listing them.
+///
+/// # Errors
+///
+/// Returns [`GiftCircleError`] when validation fails.
+pub fn enumerate_circles(people: &People, mode: GiftMode) -> Result<Circles> {
+    let validated = validate_people(people, mode)?;
+    let rules = Rules::new(people, &validated)?;
+    Ok(Circles::new(people, rules, mode.min_cycle_length()))
+}
+
+/// Iterator returned by [`enumerate_circles`], searching depth first.
+pub struct Circles {
+    people: People,
+    rules: Rules,
+    min_cycle_length: Option<usize>,
+    /// For one circle, the people so far in gift order starting with person 0; for
+    /// loops, each giver's recipient so far, giver by giver.
+    chosen: Vec<usize>,
+    /// Who gives to each person placed so far.
+    giver_of: Vec<Option<usize>>,
+    /// Choices left to try at each step, last one first.
+    frames: Vec<Vec<usize>>,
+}
+
+impl Circles {
+    fn new(people: &People, rules: Rules, min_cycle_length: Option<usize>) -> Self {
+        let mut circles = Circles {
+            people: people.clone(),
+            rules,
+            min_cycle_length,
+            chosen: Vec::with_capacity(people.len()),
+            giver_of: vec![None; people.len()],
+            frames: Vec::new(),
+        };
+        if circles.min_cycle_length.is_none() {
+            circles.chosen.push(0);
+        }
+        circles.frames.push(circles.candidates());
+        circles
+    }
+
+    /// The next set of loops, each listed in gift order.
+    fn next_cycles(&mut self) -> Option<Vec<Vec<usize>>> {
+        loop {
+            let frame = self.frames.last_mut()?;
+            let Some(recipient) = frame.pop() else {
+                self.frames.pop();
+                self.unplace();
+                continue;
+            };
+            self.place(recipient);
+            if let Some(cycles) = self.complete() {
+                self.unplace();
+                return Some(cycles);
             }
+            let candidates = self.candidates();
+            self.frames.push(candidates);
+        }
+    }
 
-            Ok(GiftCircleOutput {
-                people: from_people.assign_from_path(&path),
-                attempts: attempt_count,
-                used_groups: mode.uses_groups(),
+    /// The giver choosing a recipient next.
+    fn next_giver(&self) -> usize {
+        match self.min_cycle_length {
+            None => self.chosen[self.chosen.len() - 1],
+            Some(_) => self.chosen.len(),
+        }
+    }
+
+    /// The first person in the chain of gifts ending at `giver`, and how many it holds.
+    fn chain_to(&self, giver: usize) -> (usize, usize) {
+        let mut head = giver;
+        let mut length = 1;
+        while let Some(previous) = self.giver_of[head] {
+            head = previous;
+            length += 1;
+        }
+        (head, length)
+    }
+
+    fn candidates(&self) -> Vec<usize> {
+        let len = self.people.len();
+        if self.chosen.len() == len {
+            return Vec::new();
+        }
+        let giver = self.next_giver();
+        let (head, length) = self.chain_to(giver);
+        (0..len)
+            .rev()
+            .filter(|&recipient| {
+                self.giver_of[recipient].is_none()
+                    && self.rules.allows(giver, recipient)
+                    && match self.min_cycle_length {
+                        // Person 0 is only given to once the circle is complete.
+                        None => recipient != 0,
+                        Some(min_cycle_length) => recipient != head || length >= min_cycle_length,
+                    }
             })
+            .collect()
+    }
+
+    fn place(&mut self, recipient: usize) {
+        let giver = self.next_giver();
+        self.giver_of[recipient] = Some(giver);
+        self.chosen.push(recipient);
+    }
+
+    fn unplace(&mut self) {
+        if let Some(recipient) = self.chosen.pop() {
+            self.giver_of[recipient] = None;
         }
     }
+
+    /// The assignments found, when everyone has been placed.
+    fn complete(&self) -> Option<Vec<Vec<usize>>> {
+        match self.min_cycle_length {
+            None => (self.chosen.len() == self.people.len()
+                && self.rules.allows(self.chosen[self.chosen.len() - 1], 0))
+            .then(|| vec![self.chosen.clone()]),
+            Some(_) => (self.chosen.len() == self.people.len())
+                .then(|| cycles_from_recipients(&self.chosen)),
+        }
+    }
+}
+
+impl Iterator for Circles {
+    type Item = Vec<Person>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_cycles()
+            .map(|cycles| self.people.assign_from_cycles(&cycles))
+    }
 }
 
 #[deprecated(note = "renamed to `generate`")]
@@ -157,6 +831,8 @@ pub fn get_gift_circle_with_rng(
 #[cfg(test)]
 #[allow(clippy::unwrap_used, clippy::expect_used)]
 mod tests {
+    use std::collections::HashMap;
+
     use rand::rngs::StdRng;
     use rand::SeedableRng;
 
@@ -229,6 +905,26 @@ mod tests {
         assert_eq!(output_a.people, output_b.people);
     }
 
+    #[test]
+    fn generate_with_seed_records_metadata() {
+        let people = People::from(vec![
+            Participant::new("Father", 1),
+            Participant::new("Mother", 1),
+            Participant::new("Son", 2),
+            Participant::new("Daughter", 2),
+            Participant::new("Grandpa", 3),
+        ]);
+        let options = GenerateOptions::from(GiftMode::Grouped);
+        let output_a = generate_with_seed(&people, &options, 2024).unwrap();
+        let output_b = generate_with_seed(&people, &options, 2024).unwrap();
+        assert_eq!(output_a, output_b);
+
+        let metadata = output_a.metadata.unwrap();
+        assert_eq!(metadata.seed, 2024);
+        assert_eq!(metadata.mode, GiftMode::Grouped);
+        assert_eq!(metadata.version, env!("CARGO_PKG_VERSION"));
+    }
+
     #[test]
     fn generate_errors_with_too_few_entries() {
         let people = People::from(vec![
@@ -253,6 +949,528 @@ mod tests {
         let err = generate(&people, GiftMode::Grouped).unwrap_err();
         assert!(matches!(err, GiftCircleError::DuplicateNames(_)));
     }
+
+    #[test]
+    fn generate_derangement_without_swaps() {
+        let people = People::from(vec![
+            Participant::new("Father", 1),
+            Participant::new("Mother", 1),
+            Participant::new("Son", 2),
+            Participant::new("Daughter", 2),
+            Participant::new("Grandpa", 3),
+            Participant::new("Grandma", 3),
+        ]);
+        let mode = GiftMode::Derangement {
+            use_groups: true,
+            min_cycle_length: 3,
+        };
+        for seed in 0..20 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let output = generate_with_rng(&people, mode, &mut rng).unwrap();
+            assert!(output.used_groups);
+            assert_valid_assignments(&output);
+            let recipient_of = |name: &str| {
+                output
+                    .people
+                    .iter()
+                    .find(|p| p.name == name)
+                    .map(|p| p.assigned_person_name.clone())
+                    .unwrap()
+            };
+            for person in &output.people {
+                assert_ne!(recipient_of(&person.assigned_person_name), person.name);
+                let group_of =
+                    |name: &str| people.iter().find(|p| p.name == name).unwrap().group_number;
+                assert_ne!(
+                    group_of(&person.name),
+                    group_of(&person.assigned_person_name)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn generate_derangement_errors_with_impossible_cycle_length() {
+        let people = People::from(vec![
+            Participant::new_no_group("Father"),
+            Participant::new_no_group("Mother"),
+            Participant::new_no_group("Son"),
+        ]);
+        let mode = GiftMode::Derangement {
+            use_groups: false,
+            min_cycle_length: 4,
+        };
+        assert_eq!(
+            generate(&people, mode).unwrap_err(),
+            GiftCircleError::ImpossibleCycleLength {
+                min_cycle_length: 4,
+                count: 3
+            }
+        );
+    }
+
+    #[test]
+    fn generate_multiple_gifts_per_person() {
+        let people = People::from(vec![
+            Participant::new("Father", 1),
+            Participant::new("Mother", 1),
+            Participant::new("Son", 2),
+            Participant::new("Daughter", 2),
+            Participant::new("Grandpa", 3),
+            Participant::new("Grandma", 3),
+        ]);
+        let options = GenerateOptions {
+            mode: GiftMode::Grouped,
+            gifts_per_person: 3,
+            ..GenerateOptions::default()
+        };
+        for seed in 0..10 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let output = generate_with_options(&people, &options, &mut rng).unwrap();
+            assert_eq!(output.gifts_per_person, 3);
+            assert_eq!(output.people.len(), 18);
+            for round in output.people.chunks(people.len()) {
+                assert_valid_assignments(&GiftCircleOutput {
+                    people: round.to_vec(),
+                    ..output.clone()
+                });
+            }
+            let mut pairs: Vec<(&str, &str)> = output
+                .people
+                .iter()
+                .map(|p| (p.name.as_str(), p.assigned_person_name.as_str()))
+                .collect();
+            pairs.sort_unstable();
+            pairs.dedup();
+            assert_eq!(pairs.len(), 18);
+        }
+    }
+
+    #[test]
+    fn generate_errors_with_too_many_gifts_per_person() {
+        let people = People::from(vec![
+            Participant::new("Father", 1),
+            Participant::new("Mother", 1),
+            Participant::new("Son", 2),
+            Participant::new("Daughter", 2),
+        ]);
+        let options = GenerateOptions {
+            mode: GiftMode::Grouped,
+            gifts_per_person: 3,
+            ..GenerateOptions::default()
+        };
+        let err = generate_with_options(&people, &options, &mut rand::rng()).unwrap_err();
+        assert_eq!(
+            err,
+            GiftCircleError::TooManyGiftsPerPerson {
+                requested: 3,
+                max: 2
+            }
+        );
+    }
+
+    #[test]
+    fn generate_errors_when_every_pair_cannot_split_into_circles() {
+        for (len, gifts_per_person) in [(4, 3), (6, 5)] {
+            let people: People = (0..len)
+                .map(|index| Participant::new_no_group(&format!("p{index}")))
+                .collect();
+            let options = GenerateOptions {
+                gifts_per_person,
+                ..GenerateOptions::default()
+            };
+            let err = generate_with_options(&people, &options, &mut StdRng::seed_from_u64(1))
+                .unwrap_err();
+            assert_eq!(err, GiftCircleError::ImpossibleRounds { gifts_per_person });
+        }
+
+        let five: People = (0..5)
+            .map(|index| Participant::new_no_group(&format!("p{index}")))
+            .collect();
+        let options = GenerateOptions {
+            gifts_per_person: 4,
+            ..GenerateOptions::default()
+        };
+        let output = generate_with_options(&five, &options, &mut StdRng::seed_from_u64(1)).unwrap();
+        assert_eq!(output.people.len(), 20);
+    }
+
+    #[test]
+    fn generate_errors_with_no_gifts_per_person() {
+        let people = People::from(vec![
+            Participant::new_no_group("Father"),
+            Participant::new_no_group("Mother"),
+            Participant::new_no_group("Son"),
+        ]);
+        let options = GenerateOptions {
+            gifts_per_person: 0,
+            ..GenerateOptions::default()
+        };
+        let err = generate_with_options(&people, &options, &mut rand::rng()).unwrap_err();
+        assert_eq!(err, GiftCircleError::NoGiftsPerPerson);
+    }
+
+    #[test]
+    fn generate_honours_exclusions() {
+        let mut people = People::from(vec![
+            Participant::new("Father", 1),
+            Participant::new("Mother", 1),
+            Participant::new("Son", 2),
+            Participant::new("Daughter", 2),
+            Participant::new("Grandma", 3),
+        ]);
+        people.exclude("Father", "Son");
+        people.exclude("Son", "Father");
+        for seed in 0..20 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let output = generate_with_rng(&people, GiftMode::Grouped, &mut rng).unwrap();
+            assert_valid_assignments(&output);
+            for person in &output.people {
+                assert!(!matches!(
+                    (person.name.as_str(), person.assigned_person_name.as_str()),
+                    ("Father", "Son") | ("Son", "Father")
+                ));
+            }
+        }
+    }
+
+    #[test]
+    fn generate_honours_exclusions_without_groups() {
+        let people = People::from(vec![
+            Participant::new_no_group("Father"),
+            Participant::new_no_group("Mother"),
+            Participant::new_no_group("Son"),
+        ])
+        .with_exclusions([Exclusion::new("Father", "Mother")]);
+        let mut rng = StdRng::seed_from_u64(5);
+        let output = generate_with_rng(&people, GiftMode::Plain, &mut rng).unwrap();
+        let father = output.people.iter().find(|p| p.name == "Father").unwrap();
+        assert_eq!(father.assigned_person_name, "Son");
+    }
+
+    #[test]
+    fn generate_errors_with_impossible_exclusions() {
+        let mut people = People::from(vec![
+            Participant::new_no_group("Father"),
+            Participant::new_no_group("Mother"),
+            Participant::new_no_group("Son"),
+        ]);
+        people.exclude("Father", "Mother");
+        people.exclude("Father", "Son");
+        let err = generate(&people, GiftMode::Plain).unwrap_err();
+        assert_eq!(err, GiftCircleError::ImpossibleExclusions);
+    }
+
+    /// Forty people where P1 to P14 may only give to P1 to P13.
+    fn too_few_recipients() -> People {
+        let mut people: People = (1..=40)
+            .map(|number| Participant::new_no_group(&format!("P{number}")))
+            .collect();
+        for giver in 1..=14 {
+            for recipient in 14..=40 {
+                people.exclude(format!("P{giver}"), format!("P{recipient}"));
+            }
+        }
+        people
+    }
+
+    #[test]
+    fn generate_errors_quickly_when_givers_outnumber_their_recipients() {
+        let err = generate(&too_few_recipients(), GiftMode::Plain).unwrap_err();
+        assert_eq!(err, GiftCircleError::ImpossibleExclusions);
+        for min_cycle_length in [2, 3] {
+            let mode = GiftMode::Derangement {
+                use_groups: false,
+                min_cycle_length,
+            };
+            let err = generate(&too_few_recipients(), mode).unwrap_err();
+            assert_eq!(err, GiftCircleError::ImpossibleExclusions);
+        }
+    }
+
+    #[test]
+    fn generate_avoids_history_pairs() {
+        let people = People::from(vec![
+            Participant::new_no_group("Father"),
+            Participant::new_no_group("Mother"),
+            Participant::new_no_group("Son"),
+            Participant::new_no_group("Daughter"),
+        ]);
+        let mut history = History::new();
+        history.push_year([
+            Exclusion::new("Father", "Mother"),
+            Exclusion::new("Mother", "Son"),
+        ]);
+        history.push_year([Exclusion::new("Father", "Son")]);
+        let options = GenerateOptions {
+            history,
+            ..GenerateOptions::default()
+        };
+        for seed in 0..20 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let output = generate_with_options(&people, &options, &mut rng).unwrap();
+            assert_valid_assignments(&output);
+            assert!(output.relaxed_history.is_empty());
+            let father = output.people.iter().find(|p| p.name == "Father").unwrap();
+            assert_eq!(father.assigned_person_name, "Daughter");
+            let mother = output.people.iter().find(|p| p.name == "Mother").unwrap();
+            assert_ne!(mother.assigned_person_name, "Son");
+        }
+    }
+
+    #[test]
+    fn generate_relaxes_oldest_history_first() {
+        let people = People::from(vec![
+            Participant::new_no_group("Father"),
+            Participant::new_no_group("Mother"),
+            Participant::new_no_group("Son"),
+        ]);
+        let mut history = History::new();
+        history.push_year([Exclusion::new("Father", "Mother")]);
+        history.push_year([Exclusion::new("Father", "Son")]);
+        let options = GenerateOptions {
+            history,
+            ..GenerateOptions::default()
+        };
+        let mut rng = StdRng::seed_from_u64(1);
+        let output = generate_with_options(&people, &options, &mut rng).unwrap();
+        assert_eq!(
+            output.relaxed_history,
+            vec![Exclusion::new("Father", "Son")]
+        );
+    }
+
+    #[test]
+    fn generate_errors_with_unknown_exclusion_name() {
+        let mut people = People::from(vec![
+            Participant::new_no_group("Father"),
+            Participant::new_no_group("Mother"),
+            Participant::new_no_group("Son"),
+        ]);
+        people.exclude("Father", "Grandma");
+        let err = generate(&people, GiftMode::Plain).unwrap_err();
+        assert_eq!(
+            err,
+            GiftCircleError::UnknownExclusionName("Grandma".to_string())
+        );
+    }
+
+    /// Fraction of all valid assignments in which each giver gives to each recipient.
+    fn exact_pair_probabilities(people: &People, mode: GiftMode) -> HashMap<(String, String), f64> {
+        let circles: Vec<Vec<Person>> = enumerate_circles(people, mode).unwrap().collect();
+        let mut probabilities = HashMap::new();
+        for person in circles.iter().flatten() {
+            *probabilities
+                .entry((person.name.clone(), person.assigned_person_name.clone()))
+                .or_insert(0.0) += 1.0 / circles.len() as f64;
+        }
+        probabilities
+    }
+
+    /// Largest gap between pair frequencies over uniformly sampled draws and the exact
+    /// probabilities, in standard errors.
+    fn largest_pair_deviation(people: &People, mode: GiftMode, draws: usize) -> f64 {
+        let options = GenerateOptions {
+            mode,
+            sampling: Sampling::Uniform,
+            ..GenerateOptions::default()
+        };
+        let mut rng = StdRng::seed_from_u64(2023);
+        let mut counts: HashMap<(String, String), usize> = HashMap::new();
+        for _ in 0..draws {
+            let output = generate_with_options(people, &options, &mut rng).unwrap();
+            for person in output.people {
+                *counts
+                    .entry((person.name, person.assigned_person_name))
+                    .or_insert(0) += 1;
+            }
+        }
+
+        let probabilities = exact_pair_probabilities(people, mode);
+        assert!(counts.keys().all(|pair| probabilities.contains_key(pair)));
+        probabilities
+            .iter()
+            .map(|(pair, &probability)| {
+                let observed = counts.get(pair).copied().unwrap_or(0) as f64 / draws as f64;
+                let standard_error = (probability * (1.0 - probability) / draws as f64).sqrt();
+                (observed - probability).abs() / standard_error
+            })
+            .fold(0.0, f64::max)
+    }
+
+    #[test]
+    fn uniform_sampling_matches_exact_pair_frequencies() {
+        let people = People::from(vec![
+            Participant::new("Father", 1),
+            Participant::new("Mother", 1),
+            Participant::new("Grandpa", 1),
+            Participant::new("Son", 2),
+            Participant::new("Daughter", 2),
+            Participant::new("Aunt", 3),
+        ]);
+        assert!(largest_pair_deviation(&people, GiftMode::Grouped, 20_000) < 4.5);
+        let loops = GiftMode::Derangement {
+            use_groups: true,
+            min_cycle_length: 2,
+        };
+        assert!(largest_pair_deviation(&people, loops, 20_000) < 4.5);
+    }
+
+    #[test]
+    fn count_circles_counts_each_circle_once() {
+        let plain: Vec<Participant> = ["Father", "Mother", "Son", "Daughter", "Aunt"]
+            .into_iter()
+            .map(Participant::new_no_group)
+            .collect();
+        let plain = People::from(plain);
+        assert_eq!(count_circles(&plain, GiftMode::Plain).unwrap(), 24);
+
+        let grouped = People::from(vec![
+            Participant::new("Father", 1),
+            Participant::new("Mother", 1),
+            Participant::new("Son", 2),
+            Participant::new("Daughter", 2),
+        ]);
+        assert_eq!(count_circles(&grouped, GiftMode::Grouped).unwrap(), 2);
+
+        let four = People::from(plain.iter().take(4).cloned().collect::<Vec<_>>());
+        let derangement = |min_cycle_length| GiftMode::Derangement {
+            use_groups: false,
+            min_cycle_length,
+        };
+        assert_eq!(count_circles(&four, derangement(2)).unwrap(), 9);
+        assert_eq!(count_circles(&four, derangement(3)).unwrap(), 6);
+
+        let crowd = People::from(
+            (0..17)
+                .map(|index| Participant::new_no_group(&format!("p{index}")))
+                .collect::<Vec<_>>(),
+        );
+        assert_eq!(
+            count_circles(&crowd, GiftMode::Plain).unwrap_err(),
+            GiftCircleError::TooManyToCount { count: 17, max: 16 }
+        );
+        let estimate =
+            estimate_circles(&crowd, GiftMode::Plain, 100, &mut StdRng::seed_from_u64(1)).unwrap();
+        // Every order is a valid plain circle, so the estimate is exactly 16!.
+        assert!((estimate.circles - 20_922_789_888_000.0).abs() < 1.0);
+        assert_eq!(estimate.fitting_orders, 100);
+    }
+
+    #[test]
+    fn enumerate_circles_lists_every_valid_assignment_once() {
+        let mut people = People::from(vec![
+            Participant::new("Father", 1),
+            Participant::new("Mother", 1),
+            Participant::new("Grandpa", 1),
+            Participant::new("Son", 2),
+            Participant::new("Daughter", 2),
+            Participant::new("Aunt", 3),
+        ]);
+        people.exclude("Son", "Aunt");
+        for mode in [
+            GiftMode::Plain,
+            GiftMode::Grouped,
+            GiftMode::Derangement {
+                use_groups: true,
+                min_cycle_length: 2,
+            },
+            GiftMode::Derangement {
+                use_groups: false,
+                min_cycle_length: 3,
+            },
+        ] {
+            let validated = validate_people(&people, mode).unwrap();
+            let rules = Rules::new(&people, &validated).unwrap();
+            let index_of = |name: &str| people.iter().position(|p| p.name == name).unwrap();
+
+            let mut seen = HashSet::new();
+            for assignments in enumerate_circles(&people, mode).unwrap() {
+                let mut recipients = vec![usize::MAX; people.len()];
+                for person in &assignments {
+                    recipients[index_of(&person.name)] = index_of(&person.assigned_person_name);
+                }
+                let min_cycle_length = mode.min_cycle_length().unwrap_or(people.len());
+                assert!(rules.is_valid_derangement(&recipients, min_cycle_length));
+                assert!(seen.insert(recipients), "{mode} listed twice");
+            }
+            assert_eq!(
+                seen.len() as u64,
+                count_circles(&people, mode).unwrap(),
+                "{mode}"
+            );
+        }
+    }
+
+    #[test]
+    fn uniform_sampling_refuses_more_than_it_can_count_without_relaxing_history() {
+        let participants: Vec<Participant> = (0..30)
+            .map(|index| Participant::new(&format!("p{index}"), index % 2 + 1))
+            .collect();
+        let people = People::from(participants);
+        for mode in [
+            GiftMode::Grouped,
+            GiftMode::Derangement {
+                use_groups: true,
+                min_cycle_length: 2,
+            },
+        ] {
+            let mut history = History::new();
+            history.push_year([Exclusion::new("p0", "p1")]);
+            let options = GenerateOptions {
+                mode,
+                history,
+                sampling: Sampling::Uniform,
+                ..GenerateOptions::default()
+            };
+            let err = generate_with_options(&people, &options, &mut StdRng::seed_from_u64(1))
+                .unwrap_err();
+            assert_eq!(
+                err,
+                GiftCircleError::TooManyForUniform { count: 30, max: 16 },
+                "{mode}"
+            );
+        }
+    }
+
+    #[test]
+    fn uniform_sampling_handles_tight_loops_at_the_limit() {
+        // Two groups of eight leave fewer than one random permutation in 13,000 valid.
+        let participants: Vec<Participant> = (0..16)
+            .map(|index| Participant::new(&format!("p{index}"), index % 2 + 1))
+            .collect();
+        let people = People::from(participants);
+        let options = GenerateOptions {
+            mode: GiftMode::Derangement {
+                use_groups: true,
+                min_cycle_length: 3,
+            },
+            sampling: Sampling::Uniform,
+            ..GenerateOptions::default()
+        };
+        let output = generate_with_options(&people, &options, &mut StdRng::seed_from_u64(6))
+            .expect("valid fixture");
+        assert_valid_assignments(&output);
+        assert_eq!(output.attempts, 1);
+    }
+
+    #[test]
+    fn uniform_sampling_reports_impossible_rules() {
+        let mut people = People::from(vec![
+            Participant::new_no_group("Father"),
+            Participant::new_no_group("Mother"),
+            Participant::new_no_group("Son"),
+        ]);
+        people.exclude("Father", "Mother");
+        people.exclude("Father", "Son");
+        let options = GenerateOptions {
+            sampling: Sampling::Uniform,
+            ..GenerateOptions::default()
+        };
+        let err =
+            generate_with_options(&people, &options, &mut StdRng::seed_from_u64(1)).unwrap_err();
+        assert_eq!(err, GiftCircleError::ImpossibleExclusions);
+    }
 }
 
 #[cfg(test)]
@@ -273,6 +1491,7 @@ mod proptests {
                     name: format!("g{group}-p{index}"),
                     email_address: None,
                     group_number: Some(group),
+                    ..Participant::default()
                 });
             }
         }
@@ -296,5 +1515,36 @@ mod proptests {
             prop_assert_eq!(output.people.len(), people.len());
             prop_assert!(output.attempts <= 500);
         }
+
+        #[test]
+        fn feasible_multi_group_layouts_always_generate(
+            sizes in proptest::collection::vec(1usize..=5, 2..=5),
+            seed in any::<u64>(),
+        ) {
+            let counts: Vec<(u16, usize)> = (1u16..).zip(sizes).collect();
+            let people = grouped_people_from_counts(&counts);
+            prop_assume!(people.len() > 2);
+            let feasible = GroupedPeople::try_from(&people).is_ok();
+
+            let mut rng = StdRng::seed_from_u64(seed);
+            match generate_with_rng(&people, GiftMode::Grouped, &mut rng) {
+                Ok(output) => {
+                    prop_assert!(feasible);
+                    let index_of = |name: &str| {
+                        people.iter().position(|p| p.name == name).unwrap_or(usize::MAX)
+                    };
+                    let path: Vec<usize> =
+                        output.people.iter().map(|p| index_of(&p.name)).collect();
+                    let grouped = GroupedPeople::try_from(&people)
+                        .map_err(|err| TestCaseError::fail(format!("{err:?}")))?;
+                    prop_assert!(grouped.is_valid_gift_circle(&path));
+                }
+                Err(err) => {
+                    prop_assert!(!feasible);
+                    let is_layout_error = matches!(err, GiftCircleError::ImpossibleGroupLayout { .. });
+                    prop_assert!(is_layout_error, "{:?}", err);
+                }
+            }
+        }
     }
 }
@@ -1,11 +1,21 @@
 #[derive(Debug, PartialEq, Eq)]
 pub struct Group {
     pub number: u16,
+    /// Group name from the input, or the number written out for numeric groups.
+    pub label: String,
     pub size: u16,
 }
 
 impl Group {
     pub fn new(number: u16, size: u16) -> Self {
-        Group { number, size }
+        Self::labelled(number, number.to_string(), size)
+    }
+
+    pub fn labelled(number: u16, label: impl Into<String>, size: u16) -> Self {
+        Group {
+            number,
+            label: label.into(),
+            size,
+        }
     }
 }
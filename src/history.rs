@@ -0,0 +1,42 @@
+use crate::exclusion::Exclusion;
+use crate::person::Person;
+
+/// Giver→recipient pairs from previous draws, most recent year first.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct History {
+    years: Vec<Vec<Exclusion>>,
+}
+
+impl History {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the pairs from the next older year of history.
+    pub fn push_year(&mut self, pairs: impl IntoIterator<Item = Exclusion>) {
+        self.years.push(pairs.into_iter().collect());
+    }
+
+    /// Add the assignments from a previous year's output rows as the next older year.
+    pub fn push_year_from_people<'a>(&mut self, people: impl IntoIterator<Item = &'a Person>) {
+        self.push_year(
+            people
+                .into_iter()
+                .map(|person| Exclusion::new(&person.name, &person.assigned_person_name)),
+        );
+    }
+
+    /// Keep only the most recent `years` years of history.
+    pub fn truncate(&mut self, years: usize) {
+        self.years.truncate(years);
+    }
+
+    /// Pairs for each year, most recent first.
+    pub fn years(&self) -> &[Vec<Exclusion>] {
+        &self.years
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.years.iter().all(Vec::is_empty)
+    }
+}
@@ -6,17 +6,45 @@
 )]
 
 pub mod args;
+pub mod commitment;
+pub mod config;
+pub mod diagnostic;
+pub mod email;
+pub mod encryption;
 pub mod error;
+pub mod event;
+pub mod exclusion;
+pub mod format;
 pub mod gift_circle;
 pub mod group;
+pub mod history;
+pub mod lookup;
 pub mod mode;
 pub mod people;
 pub mod person;
+pub mod repair;
+pub mod sealed;
+#[cfg(feature = "server")]
+pub mod server;
+pub mod simulation;
+mod solver;
+mod uniform;
+pub mod verify;
 
-pub use error::GiftCircleError;
-pub use gift_circle::{generate, generate_with_rng, GiftCircleOutput};
+pub use diagnostic::Diagnostic;
+pub use error::{GiftCircleError, GroupLayoutProblem};
+pub use event::Event;
+pub use exclusion::Exclusion;
+pub use gift_circle::{
+    count_circles, enumerate_circles, estimate_circles, generate, generate_with_options,
+    generate_with_rng, generate_with_seed, CircleEstimate, Circles, DrawMetadata, GenerateOptions,
+    GiftCircleOutput,
+};
 #[allow(deprecated)]
 pub use gift_circle::{get_gift_circle, get_gift_circle_with_rng};
-pub use mode::GiftMode;
+pub use history::History;
+pub use mode::{GiftMode, Sampling};
 pub use people::{GroupedPeople, People};
 pub use person::{Participant, Person};
+pub use repair::{repair, RepairOutput};
+pub use verify::{verify, Violation};
@@ -0,0 +1,168 @@
+//! Organiser-blind draws: each giver gets a private lookup code, and a public table maps
+//! a hash of every code to that giver's recipients. The table can be posted anywhere,
+//! since it shows who receives gifts but not who gives them.
+
+use rand::prelude::{IndexedRandom, Rng};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::person::Person;
+use crate::sealed;
+
+/// Crockford's base 32, which leaves out I, L, O and U to avoid misreading.
+const CODE_ALPHABET: &[u8] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+/// Characters in a code, giving 80 random bits.
+const CODE_LENGTH: usize = 16;
+const HASH_PREFIX: &str = "gift_circle lookup v1:";
+
+/// One giver's private lookup code.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GiverCode {
+    /// File name stem for this giver's code file, unique within one draw.
+    #[serde(skip)]
+    pub slug: String,
+    pub name: String,
+    pub lookup_code: String,
+}
+
+/// One row of the public table.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LookupEntry {
+    /// Hex SHA-256 hash of a giver's lookup code.
+    pub code_hash: String,
+    /// One of that giver's recipients.
+    pub recipient: String,
+}
+
+/// Codes to hand out privately and the table to publish.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LookupCodes {
+    /// One code per giver, in the order givers first appear in the assignments.
+    pub codes: Vec<GiverCode>,
+    /// One entry per assignment, sorted by hash so the order gives nothing away.
+    pub table: Vec<LookupEntry>,
+}
+
+/// A new random code, written as four groups of four characters.
+pub fn new_code(rng: &mut impl Rng) -> String {
+    let characters: Vec<char> = (0..CODE_LENGTH)
+        .filter_map(|_| CODE_ALPHABET.choose(rng).map(|&byte| char::from(byte)))
+        .collect();
+    characters
+        .chunks(4)
+        .map(|chunk| chunk.iter().collect::<String>())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// Upper-case a typed code and drop separators, reading I and L as 1 and O as 0.
+pub fn normalize_code(code: &str) -> String {
+    code.chars()
+        .filter(|character| character.is_ascii_alphanumeric())
+        .map(|character| match character.to_ascii_uppercase() {
+            'I' | 'L' => '1',
+            'O' => '0',
+            character => character,
+        })
+        .collect()
+}
+
+/// The hash published in the table for `code`.
+pub fn hash_code(code: &str) -> String {
+    Sha256::digest(format!("{HASH_PREFIX}{}", normalize_code(code)))
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+/// Give every giver in `people` a code and build the public table of their recipients.
+pub fn lookup_codes(people: &[Person], rng: &mut impl Rng) -> LookupCodes {
+    let mut codes = Vec::new();
+    let mut table = Vec::new();
+    for assignment in sealed::seal(people) {
+        let lookup_code = new_code(rng);
+        let code_hash = hash_code(&lookup_code);
+        table.extend(assignment.people.iter().map(|person| LookupEntry {
+            code_hash: code_hash.clone(),
+            recipient: person.assigned_person_name.clone(),
+        }));
+        codes.push(GiverCode {
+            slug: assignment.slug,
+            name: assignment.people[0].name.clone(),
+            lookup_code,
+        });
+    }
+    table.sort_by(|a, b| (&a.code_hash, &a.recipient).cmp(&(&b.code_hash, &b.recipient)));
+    LookupCodes { codes, table }
+}
+
+/// The recipients listed in `table` for `code`; empty when the code is not there.
+pub fn lookup<'a>(table: &'a [LookupEntry], code: &str) -> Vec<&'a str> {
+    let code_hash = hash_code(code);
+    table
+        .iter()
+        .filter(|entry| entry.code_hash == code_hash)
+        .map(|entry| entry.recipient.as_str())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::person::Participant;
+
+    fn rows(pairs: &[(&str, &str)]) -> Vec<Person> {
+        pairs
+            .iter()
+            .map(|(giver, recipient)| {
+                Person::from_assignment(
+                    &Participant::new_no_group(giver),
+                    &Participant::new_no_group(recipient),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn codes_are_grouped_and_forgiving_to_type() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let code = new_code(&mut rng);
+        assert_eq!(code.len(), 19);
+        assert_eq!(code.matches('-').count(), 3);
+        assert_eq!(
+            hash_code(&code),
+            hash_code(&code.to_lowercase().replace('-', " "))
+        );
+        assert_eq!(normalize_code("ab-oi-l"), "AB011");
+    }
+
+    #[test]
+    fn each_code_finds_only_its_givers_recipients() {
+        let people = rows(&[
+            ("Ann", "Bob"),
+            ("Bob", "Cal"),
+            ("Cal", "Ann"),
+            ("Ann", "Cal"),
+            ("Bob", "Ann"),
+            ("Cal", "Bob"),
+        ]);
+        let lookup_codes = lookup_codes(&people, &mut StdRng::seed_from_u64(2));
+        assert_eq!(lookup_codes.codes.len(), 3);
+        assert_eq!(lookup_codes.table.len(), 6);
+        assert!(lookup_codes
+            .table
+            .windows(2)
+            .all(|pair| pair[0].code_hash <= pair[1].code_hash));
+
+        let ann = &lookup_codes.codes[0];
+        assert_eq!(ann.name, "Ann");
+        assert_eq!(
+            lookup(&lookup_codes.table, &ann.lookup_code),
+            vec!["Bob", "Cal"]
+        );
+        assert!(lookup(&lookup_codes.table, "0000-0000-0000-0000").is_empty());
+    }
+}
@@ -1,22 +1,117 @@
-use std::io;
+use std::env;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
 use std::process;
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
+use rand::rngs::StdRng;
+use rand::{RngExt, SeedableRng};
 
-use gift_circle::{args::Args, generate, GiftMode, Participant, People};
+use clap::CommandFactory;
+
+use gift_circle::args::{
+    Args, AuditArgs, Command, CommitArgs, DrawRulesArgs, GenerateArgs, InitArgs, LookupArgs,
+    RepairArgs, RevealArgs, SendArgs, SimulateArgs, StatsArgs, ValidateArgs, VerifyArgs,
+};
+use gift_circle::commitment;
+use gift_circle::config::{self, Config, CONFIG_FILE_NAME};
+use gift_circle::email::{self, DeliveryStatus, SmtpSettings, Template};
+use gift_circle::encryption::Key;
+use gift_circle::format::{self, Format};
+use gift_circle::lookup;
+use gift_circle::sealed;
+use gift_circle::simulation;
+use gift_circle::{
+    count_circles, estimate_circles, generate_with_seed, GenerateOptions, GiftCircleError,
+    GiftCircleOutput, GiftMode, History, People, Sampling,
+};
 
 fn run() -> Result<()> {
     let args = Args::parse_args();
 
-    let mut rdr = csv::Reader::from_path(&args.input)
-        .with_context(|| format!("Failed to read input from {}", args.input.display()))?;
+    match (args.command, args.generate) {
+        (Some(Command::Generate(generate_args)), _) => generate(generate_args),
+        (Some(Command::Validate(validate_args)), _) => validate(&validate_args),
+        (Some(Command::Verify(verify_args)), _) => verify(&verify_args),
+        (Some(Command::Repair(repair_args)), _) => repair(&repair_args),
+        (Some(Command::Stats(stats_args)), _) => stats(&stats_args),
+        (Some(Command::Simulate(simulate_args)), _) => simulate(&simulate_args),
+        (Some(Command::Reveal(reveal_args)), _) => reveal(&reveal_args),
+        (Some(Command::Send(send_args)), _) => send(send_args),
+        (Some(Command::Init(init_args)), _) => init(&init_args),
+        (Some(Command::Commit(commit_args)), _) => commit(&commit_args),
+        (Some(Command::Audit(audit_args)), _) => audit(&audit_args),
+        (Some(Command::Lookup(lookup_args)), _) => lookup(&lookup_args),
+        #[cfg(feature = "server")]
+        (Some(Command::Server(server_args)), _) => gift_circle::server::serve(&server_args.listen),
+        // Options without a subcommand are for generate, as is a bare run next to a config.
+        (None, generate_args)
+            if env::args_os().len() > 1 || Path::new(CONFIG_FILE_NAME).is_file() =>
+        {
+            generate(generate_args)
+        }
+        (None, _) => {
+            Args::command().print_help()?;
+            process::exit(2);
+        }
+    }
+}
 
-    let people: People = rdr
-        .deserialize::<Participant>()
-        .collect::<Result<Vec<Participant>, _>>()?
-        .into();
+fn generate(mut args: GenerateArgs) -> Result<()> {
+    if let Some(config) = Config::find(args.config.as_deref())? {
+        config.fill_generate_args(&mut args);
+    }
+    let args = &args;
+    if args.rules.input.is_none() {
+        bail!("No participants file; pass --input or set input in {CONFIG_FILE_NAME}");
+    }
+    if (args.print_all || args.encrypt) && args.output_dir.is_none() {
+        bail!("--print-all and --encrypt need --output-dir");
+    }
+    if args.min_cycle_length.is_some() && !args.derangement {
+        bail!("--min-cycle-length needs --derangement");
+    }
+    if args.lookup_table.is_some()
+        && (args.output_dir.is_none() || args.output.is_some() || args.print_all)
+    {
+        bail!("--lookup-table needs --output-dir and cannot be used with --output or --print-all");
+    }
 
-    let output = generate(&people, GiftMode::from(args.use_groups))?;
+    // Nobody, the organiser included, should see every assignment.
+    let sealed_only = args.output_dir.is_some() && args.output.is_none() && !args.print_all;
+
+    let (people, options) = read_rules(&args.rules, args.mode())?;
+    let mut output = if let Some(path) = &args.secret_file {
+        let secret = read_secret(path)?;
+        if args.entropy.is_empty() {
+            eprintln!("#WARN: No --entropy given, so the organiser alone decides this draw");
+        }
+        let draw = commitment::generate_committed(&people, &options, &secret, &args.entropy)?;
+        eprintln!("#INFO: Drew from commitment {}", draw.commitment);
+        draw.output
+    } else {
+        let seed = match args.seed {
+            Some(seed) => seed,
+            None => {
+                let seed = rand::rng().random();
+                if sealed_only {
+                    eprintln!(
+                        "#INFO: Not printing the seed, since it would reveal every assignment; \
+                         pass --seed to draw with one you keep yourself"
+                    );
+                } else {
+                    eprintln!("#INFO: Using seed {seed}; pass --seed={seed} to repeat this draw");
+                }
+                seed
+            }
+        };
+        generate_with_seed(&people, &options, seed)?
+    };
+    let event = args.event();
+    if !event.is_empty() {
+        output.event = Some(event);
+    }
 
     if output.used_groups {
         eprintln!(
@@ -30,18 +125,515 @@ fn run() -> Result<()> {
         );
     }
 
-    let mut wtr = csv::Writer::from_writer(io::stdout());
+    if sealed_only && !output.relaxed_history.is_empty() {
+        eprintln!(
+            "#WARN: Could not avoid repeating {} pairs from history",
+            output.relaxed_history.len()
+        );
+    } else {
+        for pair in &output.relaxed_history {
+            eprintln!(
+                "#WARN: Could not avoid repeating {} -> {} from history",
+                pair.giver, pair.recipient
+            );
+        }
+    }
+
+    let output_format = Format::resolve(args.output_format, args.output.as_deref());
+    if let (Some(path), Some(dir)) = (&args.lookup_table, &args.output_dir) {
+        let codes = lookup::lookup_codes(&output.people, &mut rand::rng());
+        let file = File::create(path)
+            .with_context(|| format!("Failed to write lookup table to {}", path.display()))?;
+        let mut writer = BufWriter::new(file);
+        format::write_lookup_table(&mut writer, &codes.table, Format::from_path(path))?;
+        writer.flush()?;
+        let participants = args.encrypt.then_some(&people);
+        let paths = sealed::write_code_files(dir, &codes.codes, participants, output_format)?;
+        eprintln!(
+            "#INFO: Wrote the lookup table to {} and {} lookup code files to {}",
+            path.display(),
+            paths.len(),
+            dir.display()
+        );
+        return Ok(());
+    }
+    if let Some(dir) = &args.output_dir {
+        let paths = if args.encrypt {
+            sealed::write_encrypted_sealed_files(
+                dir,
+                &output.people,
+                &people,
+                output.event.as_ref(),
+                output_format,
+            )?
+        } else {
+            sealed::write_sealed_files(dir, &output.people, output.event.as_ref(), output_format)?
+        };
+        eprintln!(
+            "#INFO: Wrote {} sealed assignment files to {}",
+            paths.len(),
+            dir.display()
+        );
+        if sealed_only {
+            return Ok(());
+        }
+    }
+
+    match &args.output {
+        Some(path) => {
+            let file = File::create(path)
+                .with_context(|| format!("Failed to write output to {}", path.display()))?;
+            write_output(BufWriter::new(file), &output, output_format, args)
+        }
+        None => write_output(io::stdout().lock(), &output, output_format, args),
+    }
+}
+
+fn write_output(
+    mut writer: impl Write,
+    output: &GiftCircleOutput,
+    output_format: Format,
+    args: &GenerateArgs,
+) -> Result<()> {
+    format::write_output(&mut writer, output, output_format)?;
+    if let (Format::Csv, true, Some(metadata)) =
+        (output_format, args.metadata_trailer, &output.metadata)
+    {
+        format::write_csv_trailer(&mut writer, metadata)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn validate(args: &ValidateArgs) -> Result<()> {
+    let input_format = Format::resolve(args.input_format, Some(&args.input));
+    let (participants, mut diagnostics) =
+        format::read_participants_checked(&args.input, input_format)?;
+    let mut people = People::from(participants);
+    if let Some(path) = &args.exclusions.file {
+        people = people.with_exclusions(format::read_exclusions(path)?);
+    }
+    diagnostics.extend(people.validate(args.mode.mode()));
+
+    if diagnostics.is_empty() {
+        eprintln!("#INFO: {} participants look good", people.len());
+        return Ok(());
+    }
+    for diagnostic in &diagnostics {
+        println!("{diagnostic}");
+    }
+    anyhow::bail!(
+        "Found {} problems in {}",
+        diagnostics.len(),
+        args.input.display()
+    )
+}
+
+fn verify(args: &VerifyArgs) -> Result<()> {
+    let assignments = format::read_assignments(&args.input)?;
+    let mut people = match &args.participants {
+        Some(path) => People::from(format::read_participants(path, Format::from_path(path))?),
+        None => People::from_assignments(&assignments),
+    };
+    if let Some(path) = &args.exclusions.file {
+        people = people.with_exclusions(format::read_exclusions(path)?);
+    }
+
+    let violations = gift_circle::verify(
+        &people,
+        &assignments,
+        args.mode.mode(),
+        args.gifts_per_person,
+    );
+    if violations.is_empty() {
+        eprintln!(
+            "#INFO: {} assignments for {} participants follow the rules",
+            assignments.len(),
+            people.len()
+        );
+        return Ok(());
+    }
+    for violation in &violations {
+        println!("{violation}");
+    }
+    anyhow::bail!(
+        "Found {} problems in {}",
+        violations.len(),
+        args.input.display()
+    )
+}
+
+fn repair(args: &RepairArgs) -> Result<()> {
+    let previous = format::read_output(&args.input)?;
+    let mut people = People::from(format::read_participants(
+        &args.participants,
+        Format::from_path(&args.participants),
+    )?);
+    if let Some(path) = &args.exclusions.file {
+        people = people.with_exclusions(format::read_exclusions(path)?);
+    }
+
+    let seed = args.seed.unwrap_or_else(|| rand::rng().random());
+    let mut rng = StdRng::seed_from_u64(seed);
+    let repaired = gift_circle::repair(&previous, &people, args.mode.mode(), &mut rng)?;
+
+    for name in &repaired.removed {
+        eprintln!("#INFO: {name} dropped out");
+    }
+    eprintln!(
+        "#INFO: {} of {} givers keep their recipient",
+        people.len() - repaired.notify.len(),
+        people.len()
+    );
+    for name in &repaired.notify {
+        eprintln!("#INFO: Tell {name} about their new recipient");
+    }
+
+    let output_format = Format::resolve(args.output_format, args.output.as_deref());
+    if let Some(dir) = &args.output_dir {
+        let changed: Vec<_> = repaired
+            .output
+            .people
+            .iter()
+            .filter(|person| repaired.notify.contains(&person.name))
+            .cloned()
+            .collect();
+        let paths = sealed::write_sealed_files(
+            dir,
+            &changed,
+            repaired.output.event.as_ref(),
+            output_format,
+        )?;
+        eprintln!(
+            "#INFO: Wrote {} sealed assignment files to {}",
+            paths.len(),
+            dir.display()
+        );
+    }
+
+    match &args.output {
+        Some(path) => {
+            let file = File::create(path)
+                .with_context(|| format!("Failed to write output to {}", path.display()))?;
+            Ok(format::write_output(
+                BufWriter::new(file),
+                &repaired.output,
+                output_format,
+            )?)
+        }
+        None if args.output_dir.is_some() => Ok(()),
+        None => Ok(format::write_output(
+            io::stdout().lock(),
+            &repaired.output,
+            output_format,
+        )?),
+    }
+}
+
+/// Random orders checked when estimating how many circles there are.
+const ESTIMATE_SAMPLES: usize = 100_000;
+
+fn stats(args: &StatsArgs) -> Result<()> {
+    let (people, options) = read_rules(&args.rules, args.mode.mode())?;
+    if let Some(giver) = &args.giver {
+        if !people.iter().any(|participant| participant.name == *giver) {
+            bail!("{giver} is not among the participants");
+        }
+    }
+
+    // The count only follows the groups and exclusions; history and extra gifts show up in
+    // the pairing chances below.
+    let mode = options.mode;
+    let counted = count_circles(&people, mode);
+    // Uniform draws count every circle first, so they fail for the same crowds; say so
+    // before printing half a report.
+    if args.draws > 0 && options.sampling == Sampling::Uniform {
+        if let Err(GiftCircleError::TooManyToCount { count, max }) = counted {
+            return Err(GiftCircleError::TooManyForUniform { count, max }.into());
+        }
+    }
+
+    let groups = people.groups()?;
+    println!("participants: {}", people.len());
+    println!("groups: {}", groups.len());
+    for group in &groups {
+        println!("  {}: {}", group.label, group.size);
+    }
+    let ungrouped = people.len() - groups.iter().map(|g| usize::from(g.size)).sum::<usize>();
+    if ungrouped > 0 {
+        println!("without a group: {ungrouped}");
+    }
+    if let Some(largest) = groups.first() {
+        println!(
+            "largest group: {} with {}; a grouped circle allows at most {}",
+            largest.label,
+            largest.size,
+            people.len() / 2
+        );
+    }
+
+    let seed = args.seed.unwrap_or_else(|| rand::rng().random());
+    let mut rng = StdRng::seed_from_u64(seed);
+    match counted {
+        Ok(0) => {
+            println!("possible circles: 0");
+            return Ok(());
+        }
+        Ok(count) => println!("possible circles: {count}"),
+        Err(GiftCircleError::TooManyToCount { .. }) => {
+            let estimate = estimate_circles(&people, mode, ESTIMATE_SAMPLES, &mut rng)?;
+            if estimate.fitting_orders > 0 {
+                println!(
+                    "possible circles: about {:.3e} ({} of {ESTIMATE_SAMPLES} random orders fit the rules; the fewer fit, the rougher the estimate)",
+                    estimate.circles, estimate.fitting_orders
+                );
+            } else {
+                // Too few random orders fit the rules to estimate, so look for one circle.
+                if let Err(err) = generate_with_seed(&people, &GenerateOptions::from(mode), seed) {
+                    println!("possible circles: none; {err}");
+                    return Ok(());
+                }
+                println!(
+                    "possible circles: at least 1 (none of {ESTIMATE_SAMPLES} random orders fit the rules, so no estimate)"
+                );
+            }
+        }
+        Err(err) => {
+            println!("possible circles: none; {err}");
+            return Ok(());
+        }
+    }
+    if args.draws == 0 {
+        return Ok(());
+    }
 
-    for person in output.people {
-        wtr.serialize(person)?;
+    let report = simulation::simulate(&people, &options, args.draws, seed)?;
+    println!(
+        "chance of each pairing over {} {} draws:",
+        args.draws, options.sampling
+    );
+    for (giver, row) in report.names.iter().zip(&report.pair_counts) {
+        if args.giver.as_ref().is_some_and(|name| name != giver) {
+            continue;
+        }
+        for (recipient, &count) in report.names.iter().zip(row) {
+            if count > 0 {
+                println!(
+                    "  {giver} -> {recipient}: {:.1}%",
+                    100.0 * count as f64 / args.draws as f64
+                );
+            }
+        }
     }
+    Ok(())
+}
 
-    Ok(wtr.flush()?)
+fn simulate(args: &SimulateArgs) -> Result<()> {
+    let (people, options) = read_rules(&args.rules, args.mode.mode())?;
+    let seed = match args.seed {
+        Some(seed) => seed,
+        None => {
+            let seed = rand::rng().random();
+            eprintln!("#INFO: Using seed {seed}; pass --seed={seed} to repeat this report");
+            seed
+        }
+    };
+    let report = simulation::simulate(&people, &options, args.draws, seed)?;
+
+    let output_format = Format::resolve(args.output_format, args.output.as_deref());
+    match &args.output {
+        Some(path) => {
+            let file = File::create(path)
+                .with_context(|| format!("Failed to write report to {}", path.display()))?;
+            let mut writer = BufWriter::new(file);
+            format::write_simulation(&mut writer, &report, output_format)?;
+            writer.flush()?;
+        }
+        None => format::write_simulation(io::stdout().lock(), &report, output_format)?,
+    }
+    Ok(())
+}
+
+fn init(args: &InitArgs) -> Result<()> {
+    let path = config::write_starter_config(&args.input, args.force)?;
+    eprintln!("#INFO: Wrote {}", path.display());
+    Ok(())
+}
+
+fn read_rules(args: &DrawRulesArgs, mode: GiftMode) -> Result<(People, GenerateOptions)> {
+    let Some(input) = &args.input else {
+        bail!("No participants file; pass --input");
+    };
+    let input_format = Format::resolve(args.input_format, Some(input));
+    let mut people: People = format::read_participants(input, input_format)?.into();
+    if let Some(path) = &args.exclusions.file {
+        people = people.with_exclusions(format::read_exclusions(path)?);
+    }
+
+    let mut history = History::new();
+    for path in &args.history {
+        history.push_year_from_people(&format::read_assignments(path)?);
+    }
+    if let Some(years) = args.history_years {
+        history.truncate(years);
+    }
+
+    let options = GenerateOptions {
+        mode,
+        history,
+        gifts_per_person: args.gifts_per_person.unwrap_or(1),
+        sampling: args.sampling.unwrap_or_default(),
+    };
+    Ok((people, options))
+}
+
+fn read_secret(path: &Path) -> Result<String> {
+    let secret = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read secret from {}", path.display()))?;
+    Ok(secret.trim().to_string())
+}
+
+fn commit(args: &CommitArgs) -> Result<()> {
+    let (people, options) = read_rules(&args.rules, args.mode.mode())?;
+    if args.secret_file.exists() && !args.force {
+        bail!(
+            "{} already exists; pass --force to replace it",
+            args.secret_file.display()
+        );
+    }
+
+    let secret = commitment::new_secret();
+    std::fs::write(&args.secret_file, format!("{secret}\n"))
+        .with_context(|| format!("Failed to write {}", args.secret_file.display()))?;
+    eprintln!(
+        "#INFO: Wrote the secret to {}; keep it private until gifts are exchanged",
+        args.secret_file.display()
+    );
+    println!("{}", commitment::commitment(&people, &options, &secret));
+    Ok(())
+}
+
+fn audit(args: &AuditArgs) -> Result<()> {
+    let (people, options) = read_rules(&args.rules, args.mode.mode())?;
+    let assignments = format::read_assignments(&args.assignments)?;
+    let mismatches = commitment::audit(
+        &people,
+        &options,
+        &args.secret,
+        &args.entropy,
+        &args.commitment,
+        &assignments,
+    )?;
+
+    if mismatches.is_empty() {
+        eprintln!(
+            "#INFO: The commitment and all {} assignments match",
+            assignments.len()
+        );
+        return Ok(());
+    }
+    for mismatch in &mismatches {
+        println!("{mismatch}");
+    }
+    bail!(
+        "Found {} problems in {}",
+        mismatches.len(),
+        args.assignments.display()
+    )
+}
+
+fn lookup(args: &LookupArgs) -> Result<()> {
+    let table = format::read_lookup_table(&args.table)?;
+    let recipients = lookup::lookup(&table, &args.code);
+    if recipients.is_empty() {
+        bail!(
+            "No recipient found for that code in {}; check it was typed correctly",
+            args.table.display()
+        );
+    }
+    for recipient in recipients {
+        println!("{recipient}");
+    }
+    Ok(())
+}
+
+fn reveal(args: &RevealArgs) -> Result<()> {
+    let key = match (&args.passphrase, &args.identity) {
+        (_, Some(path)) => Key::from_identity_file(path)?,
+        (Some(passphrase), None) => Key::passphrase(passphrase.as_str()),
+        (None, None) => anyhow::bail!("Provide --passphrase or --identity to reveal an assignment"),
+    };
+
+    let ciphertext = std::fs::read(&args.file)
+        .with_context(|| format!("Failed to read {}", args.file.display()))?;
+    let plaintext = key.decrypt(&ciphertext)?;
+    io::Write::write_all(&mut io::stdout().lock(), &plaintext)?;
+    Ok(())
+}
+
+fn send(mut args: SendArgs) -> Result<()> {
+    if let Some(config) = Config::find(args.config.as_deref())? {
+        config.fill_send_args(&mut args);
+    }
+    let args = &args;
+    let Some(input) = &args.input else {
+        bail!("No assignments file; pass --input or set output in {CONFIG_FILE_NAME}");
+    };
+    let Some(from) = &args.from else {
+        bail!("No sender address; pass --from or set from under [email] in {CONFIG_FILE_NAME}");
+    };
+
+    let output = format::read_output(input)?;
+    let people = output.people;
+    let from = email::parse_mailbox(from)?;
+    let mut template = Template {
+        event: args.event().or(output.event.unwrap_or_default()),
+        ..Template::default()
+    };
+    if let Some(subject) = &args.subject {
+        template.subject.clone_from(subject);
+    }
+    if let Some(path) = &args.body_template {
+        template.body = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+    }
+
+    let report = if args.dry_run {
+        email::write_eml_files(&args.eml_dir, &people, &from, &template)?
+    } else {
+        let security = args.smtp_security.unwrap_or_default();
+        let settings = SmtpSettings {
+            host: args
+                .smtp_host
+                .clone()
+                .unwrap_or_else(|| "localhost".to_string()),
+            port: args.smtp_port.unwrap_or_else(|| security.default_port()),
+            security,
+            username: args.smtp_username.clone(),
+            password: std::env::var("GIFT_CIRCLE_SMTP_PASSWORD").ok(),
+        };
+        email::send_all(&people, &from, &template, &settings.transport()?)
+    };
+
+    let mut writer = csv::Writer::from_writer(io::stdout().lock());
+    for delivery in &report {
+        writer.serialize(delivery)?;
+    }
+    writer.flush()?;
+
+    let failed = report
+        .iter()
+        .filter(|delivery| delivery.status == DeliveryStatus::Failed)
+        .count();
+    if failed > 0 {
+        anyhow::bail!("Failed to deliver {failed} of {} assignments", report.len());
+    }
+    Ok(())
 }
 
 fn main() {
     if let Err(err) = run() {
-        eprintln!("{err}");
+        eprintln!("{err:#}");
         process::exit(1);
     }
 }
@@ -1,15 +1,66 @@
+use std::fmt;
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
 /// Whether gift assignments must respect family group boundaries.
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
 pub enum GiftMode {
     /// Any participant may be assigned any other participant.
+    #[default]
     Plain,
     /// Participants must not give gifts to members of their own group.
     Grouped,
+    /// Nobody is assigned themselves, but assignments may form several smaller loops
+    /// instead of one circle.
+    Derangement {
+        /// Whether participants must not give gifts to members of their own group.
+        use_groups: bool,
+        /// Smallest allowed loop; 2 permits two people swapping gifts, 3 forbids it.
+        min_cycle_length: usize,
+    },
 }
 
 impl GiftMode {
     pub(crate) fn uses_groups(self) -> bool {
-        matches!(self, Self::Grouped)
+        matches!(
+            self,
+            Self::Grouped
+                | Self::Derangement {
+                    use_groups: true,
+                    ..
+                }
+        )
+    }
+
+    /// Smallest allowed loop when several loops are permitted, or `None` for one circle.
+    pub(crate) fn min_cycle_length(self) -> Option<usize> {
+        match self {
+            Self::Plain | Self::Grouped => None,
+            Self::Derangement {
+                min_cycle_length, ..
+            } => Some(min_cycle_length.max(2)),
+        }
+    }
+}
+
+impl fmt::Display for GiftMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Plain => write!(f, "plain"),
+            Self::Grouped => write!(f, "grouped"),
+            Self::Derangement {
+                use_groups,
+                min_cycle_length,
+            } => {
+                write!(f, "derangement")?;
+                if *use_groups {
+                    write!(f, " with groups")?;
+                }
+                write!(f, ", min cycle length {min_cycle_length}")
+            }
+        }
     }
 }
 
@@ -22,3 +73,31 @@ impl From<bool> for GiftMode {
         }
     }
 }
+
+/// How a draw picks among all the assignments that satisfy the rules.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Sampling {
+    /// Quick random walks that favour some valid assignments over others, falling back
+    /// to an exhaustive search.
+    #[default]
+    Fast,
+    /// Every valid assignment is equally likely, picked by counting them all, which
+    /// limits draws to 16 people.
+    Uniform,
+}
+
+impl Sampling {
+    pub(crate) fn is_fast(&self) -> bool {
+        *self == Self::Fast
+    }
+}
+
+impl fmt::Display for Sampling {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Fast => write!(f, "fast"),
+            Self::Uniform => write!(f, "uniform"),
+        }
+    }
+}
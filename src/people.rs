@@ -1,24 +1,92 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::ops::Deref;
 
-use crate::error::{GiftCircleError, Result};
+use crate::diagnostic::{Diagnostic, Location};
+use crate::error::{GiftCircleError, GroupLayoutProblem, Result};
+use crate::exclusion::Exclusion;
 use crate::group::Group;
+use crate::mode::GiftMode;
 use crate::person::{Participant, Person};
 
-/// A collection of participants loaded from the input CSV.
+/// A collection of participants loaded from the input CSV, plus any exclusion rules.
 #[derive(Clone, Debug, Default, PartialEq, Eq)]
-pub struct People(Vec<Participant>);
+pub struct People {
+    participants: Vec<Participant>,
+    exclusions: Vec<Exclusion>,
+}
 
 /// Participants validated to have a group assignment on every member.
 #[derive(Clone, Debug, PartialEq, Eq)]
-pub struct GroupedPeople(People);
+pub struct GroupedPeople {
+    people: People,
+    /// Group number for each participant, in input order.
+    numbers: Vec<u16>,
+    /// Label for each group number.
+    labels: HashMap<u16, String>,
+}
 
 impl GroupedPeople {
-    pub(crate) fn group_at(people: &People, index: usize) -> u16 {
-        match people[index].group_number {
-            Some(group) => group,
-            None => unreachable!("GroupedPeople validates every participant has a group"),
+    /// Number every participant's group: numeric labels keep their number and named
+    /// groups take the unused numbers after the largest numeric group, in order of
+    /// appearance, wrapping round to the unused numbers below it.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`GiftCircleError::TooManyGroups`] when there are more groups than numbers.
+    pub(crate) fn new_unchecked(people: People) -> Result<Self> {
+        let group_labels: Vec<String> = people
+            .participants
+            .iter()
+            .map(|participant| participant.group_label().unwrap_or_default())
+            .collect();
+        let mut labels: HashMap<u16, String> = group_labels
+            .iter()
+            .filter_map(|label| Some((label.parse::<u16>().ok()?, label.clone())))
+            .collect();
+        let mut numbers_by_label: HashMap<String, u16> = labels
+            .iter()
+            .map(|(&number, label)| (label.clone(), number))
+            .collect();
+        let max = labels.keys().max().copied().unwrap_or(0);
+        let taken: HashSet<u16> = labels.keys().copied().collect();
+        let mut unused = (max.saturating_add(1)..=u16::MAX)
+            .chain(1..max)
+            .filter(|number| !taken.contains(number));
+
+        let mut numbers = Vec::with_capacity(group_labels.len());
+        for label in &group_labels {
+            if let Ok(number) = label.parse::<u16>() {
+                numbers.push(number);
+                continue;
+            }
+            let number = match numbers_by_label.get(label) {
+                Some(&number) => number,
+                None => {
+                    let number = unused.next().ok_or(GiftCircleError::TooManyGroups {
+                        max: usize::from(u16::MAX),
+                    })?;
+                    numbers_by_label.insert(label.clone(), number);
+                    labels.insert(number, label.clone());
+                    number
+                }
+            };
+            numbers.push(number);
         }
+
+        Ok(Self {
+            people,
+            numbers,
+            labels,
+        })
+    }
+
+    pub(crate) fn group_at(&self, index: usize) -> u16 {
+        self.numbers[index]
+    }
+
+    /// The label of a group number, as given in the input.
+    pub fn group_label(&self, number: u16) -> &str {
+        self.labels.get(&number).map_or("", String::as_str)
     }
 
     fn count_groups(groups: impl Iterator<Item = u16>) -> HashMap<u16, u16> {
@@ -29,7 +97,7 @@ impl GroupedPeople {
         counts
     }
 
-    fn largest_group_from_counts(counts: &HashMap<u16, u16>) -> Option<Group> {
+    fn largest_group_from_counts(&self, counts: &HashMap<u16, u16>) -> Option<Group> {
         counts
             .iter()
             .max_by(|(left_num, left_size), (right_num, right_size)| {
@@ -37,22 +105,40 @@ impl GroupedPeople {
                     .cmp(right_size)
                     .then_with(|| right_num.cmp(left_num))
             })
-            .map(|(&number, &size)| Group::new(number, size))
+            .map(|(&number, &size)| Group::labelled(number, self.group_label(number), size))
+    }
+
+    /// Every group with its size, largest first.
+    pub fn groups(&self) -> Vec<Group> {
+        let counts = Self::count_groups(self.numbers.iter().copied());
+        let mut groups: Vec<Group> = counts
+            .into_iter()
+            .map(|(number, size)| Group::labelled(number, self.group_label(number), size))
+            .collect();
+        groups.sort_by(|left, right| {
+            right
+                .size
+                .cmp(&left.size)
+                .then_with(|| left.number.cmp(&right.number))
+        });
+        groups
     }
 
     pub fn largest_group(&self) -> Group {
-        let counts = Self::count_groups(self.0.iter().filter_map(|p| p.group_number));
-        Self::largest_group_from_counts(&counts).unwrap_or(Group::new(0, 0))
+        let counts = Self::count_groups(self.numbers.iter().copied());
+        self.largest_group_from_counts(&counts)
+            .unwrap_or(Group::new(0, 0))
     }
 
     pub fn largest_non_prev_group(&self, previous_group: u16, remaining: &[usize]) -> Group {
         let counts = Self::count_groups(
             remaining
                 .iter()
-                .filter_map(|&index| self.0[index].group_number)
+                .map(|&index| self.numbers[index])
                 .filter(|group| *group != previous_group),
         );
-        Self::largest_group_from_counts(&counts).unwrap_or(Group::new(0, 0))
+        self.largest_group_from_counts(&counts)
+            .unwrap_or(Group::new(0, 0))
     }
 
     pub fn has_possible_hamiltonian_path(&self) -> bool {
@@ -63,15 +149,15 @@ impl GroupedPeople {
         if path.is_empty() {
             return false;
         }
-        let first_group = Self::group_at(&self.0, path[0]);
-        let last_group = Self::group_at(&self.0, path[path.len() - 1]);
+        let first_group = self.group_at(path[0]);
+        let last_group = self.group_at(path[path.len() - 1]);
         first_group != last_group
     }
 
     pub fn has_no_consecutive_group_numbers(&self, path: &[usize]) -> bool {
         let mut previous_group: Option<u16> = None;
         for &index in path {
-            let group = Self::group_at(&self.0, index);
+            let group = self.group_at(index);
             if previous_group == Some(group) {
                 return false;
             }
@@ -84,6 +170,17 @@ impl GroupedPeople {
         self.first_and_last_groups_are_different(path)
             && self.has_no_consecutive_group_numbers(path)
     }
+
+    /// The largest group is too big for a circle; explain by how much and how to fix it.
+    pub fn layout_problem(&self) -> GroupLayoutProblem {
+        let largest = self.largest_group();
+        GroupLayoutProblem::new(largest.label, usize::from(largest.size), self.len())
+    }
+
+    /// The error describing why this group layout cannot form a circle.
+    pub fn impossible_layout_error(&self) -> GiftCircleError {
+        GiftCircleError::ImpossibleGroupLayout(self.layout_problem())
+    }
 }
 
 impl TryFrom<&People> for GroupedPeople {
@@ -93,9 +190,9 @@ impl TryFrom<&People> for GroupedPeople {
         if people.has_empty_group() {
             return Err(GiftCircleError::MissingGroup);
         }
-        let grouped = Self(people.clone());
+        let grouped = Self::new_unchecked(people.clone())?;
         if !grouped.has_possible_hamiltonian_path() {
-            return Err(GiftCircleError::ImpossibleGroupLayout);
+            return Err(grouped.impossible_layout_error());
         }
         Ok(grouped)
     }
@@ -105,25 +202,25 @@ impl Deref for GroupedPeople {
     type Target = People;
 
     fn deref(&self) -> &Self::Target {
-        &self.0
+        &self.people
     }
 }
 
 impl People {
     pub fn len(&self) -> usize {
-        self.0.len()
+        self.participants.len()
     }
 
     pub fn is_empty(&self) -> bool {
-        self.0.is_empty()
+        self.participants.is_empty()
     }
 
     pub fn iter(&self) -> impl Iterator<Item = &Participant> {
-        self.0.iter()
+        self.participants.iter()
     }
 
     pub fn get(&self, index: usize) -> Option<&Participant> {
-        self.0.get(index)
+        self.participants.get(index)
     }
 
     pub fn assign_from_path(&self, path: &[usize]) -> Vec<Person> {
@@ -135,20 +232,31 @@ impl People {
             .enumerate()
             .map(|(position, &giver_index)| {
                 let recipient_index = path[(position + 1) % path.len()];
-                Person::from_assignment(&self.0[giver_index], self.0[recipient_index].name.clone())
+                Person::from_assignment(
+                    &self.participants[giver_index],
+                    &self.participants[recipient_index],
+                )
             })
             .collect()
     }
 
+    /// Assign recipients around each loop in turn, listing the loops one after another.
+    pub fn assign_from_cycles(&self, cycles: &[Vec<usize>]) -> Vec<Person> {
+        cycles
+            .iter()
+            .flat_map(|cycle| self.assign_from_path(cycle))
+            .collect()
+    }
+
     pub fn has_empty_group(&self) -> bool {
-        self.0
+        self.participants
             .iter()
-            .any(|participant| participant.group_number.is_none())
+            .any(|participant| participant.group_label().is_none())
     }
 
     pub fn duplicated_names(&self) -> Vec<String> {
         let mut counts = HashMap::new();
-        for participant in &self.0 {
+        for participant in &self.participants {
             *counts.entry(participant.name.clone()).or_insert(0) += 1;
         }
 
@@ -160,6 +268,183 @@ impl People {
         duplicates
     }
 
+    /// Exclusion rules the generator must honour.
+    pub fn exclusions(&self) -> &[Exclusion] {
+        &self.exclusions
+    }
+
+    /// Forbid `giver` from ever being assigned `recipient`.
+    pub fn exclude(&mut self, giver: impl Into<String>, recipient: impl Into<String>) {
+        self.exclusions.push(Exclusion::new(giver, recipient));
+    }
+
+    /// Add several exclusion rules at once.
+    pub fn with_exclusions(mut self, exclusions: impl IntoIterator<Item = Exclusion>) -> Self {
+        self.exclusions.extend(exclusions);
+        self
+    }
+
+    /// Resolve exclusion rules into `(giver, recipient)` participant index pairs.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`GiftCircleError::UnknownExclusionName`] when a rule names someone who
+    /// is not participating.
+    pub fn excluded_index_pairs(&self) -> Result<HashSet<(usize, usize)>> {
+        let index_of = |name: &str| {
+            self.participants
+                .iter()
+                .position(|participant| participant.name == name)
+                .ok_or_else(|| GiftCircleError::UnknownExclusionName(name.to_string()))
+        };
+        self.exclusions
+            .iter()
+            .map(|exclusion| Ok((index_of(&exclusion.giver)?, index_of(&exclusion.recipient)?)))
+            .collect()
+    }
+
+    /// The givers of earlier assignments, once each in order of first appearance, with
+    /// their email addresses and groups.
+    pub fn from_assignments(assignments: &[Person]) -> Self {
+        let mut participants: Vec<Participant> = Vec::new();
+        for person in assignments {
+            if participants
+                .iter()
+                .all(|participant| participant.name != person.name)
+            {
+                participants.push(Participant {
+                    name: person.name.clone(),
+                    email_address: person.email_address.clone(),
+                    group_number: person.group_number,
+                    group: person.group.clone(),
+                    ..Participant::default()
+                });
+            }
+        }
+        Self::from(participants)
+    }
+
+    /// Groups of participants with their sizes, largest first; participants without a
+    /// group are left out.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`GiftCircleError::TooManyGroups`] when there are more groups than numbers.
+    pub fn groups(&self) -> Result<Vec<Group>> {
+        Ok(GroupedPeople::new_unchecked(self.clone())?
+            .groups()
+            .into_iter()
+            .filter(|group| !group.label.is_empty())
+            .collect())
+    }
+
+    /// Where the participant at `index` came from in the input.
+    fn location(&self, index: usize) -> Location {
+        Location {
+            entry: index + 1,
+            line: self.participants[index].line,
+        }
+    }
+
+    /// Check everything a draw in `mode` needs, reporting every problem found rather
+    /// than stopping at the first.
+    pub fn validate(&self, mode: GiftMode) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+
+        if self.len() <= 2 {
+            diagnostics.push(Diagnostic::TooFewParticipants { count: self.len() });
+        }
+
+        let mut seen: Vec<(&str, Vec<Location>)> = Vec::new();
+        for (index, participant) in self.participants.iter().enumerate() {
+            let at = self.location(index);
+            let name = participant.name.as_str();
+            if name.is_empty() {
+                diagnostics.push(Diagnostic::BlankName { at });
+            } else if name.trim().is_empty() {
+                diagnostics.push(Diagnostic::WhitespaceName {
+                    name: name.to_string(),
+                    at,
+                });
+            } else {
+                match seen.iter_mut().find(|(seen_name, _)| *seen_name == name) {
+                    Some((_, at_all)) => at_all.push(at),
+                    None => seen.push((name, vec![at])),
+                }
+            }
+
+            if mode.uses_groups() && participant.group_label().is_none() {
+                diagnostics.push(Diagnostic::MissingGroup {
+                    name: name.to_string(),
+                    at,
+                });
+            }
+
+            if let Some(email_address) = participant
+                .email_address
+                .as_deref()
+                .filter(|address| !address.trim().is_empty())
+            {
+                if email_address.parse::<lettre::Address>().is_err() {
+                    diagnostics.push(Diagnostic::MalformedEmail {
+                        name: name.to_string(),
+                        email_address: email_address.to_string(),
+                        at,
+                    });
+                }
+            }
+        }
+        diagnostics.extend(
+            seen.into_iter()
+                .filter(|(_, at)| at.len() > 1)
+                .map(|(name, at)| Diagnostic::DuplicateName {
+                    name: name.to_string(),
+                    at,
+                }),
+        );
+
+        let mut unknown: Vec<&str> = Vec::new();
+        for exclusion in &self.exclusions {
+            for name in [&exclusion.giver, &exclusion.recipient] {
+                if !self.participants.iter().any(|p| &p.name == name)
+                    && !unknown.contains(&name.as_str())
+                {
+                    unknown.push(name);
+                }
+            }
+        }
+        diagnostics.extend(
+            unknown
+                .into_iter()
+                .map(|name| Diagnostic::UnknownExclusionName {
+                    name: name.to_string(),
+                }),
+        );
+
+        if let Some(min_cycle_length) = mode.min_cycle_length() {
+            if min_cycle_length > self.len() {
+                diagnostics.push(Diagnostic::ImpossibleCycleLength {
+                    min_cycle_length,
+                    count: self.len(),
+                });
+            }
+        }
+
+        if mode.uses_groups() && !self.is_empty() && !self.has_empty_group() {
+            match GroupedPeople::new_unchecked(self.clone()) {
+                Ok(grouped) if !grouped.has_possible_hamiltonian_path() => {
+                    diagnostics.push(Diagnostic::ImpossibleGroupLayout(grouped.layout_problem()));
+                }
+                Ok(_) => {}
+                Err(_) => diagnostics.push(Diagnostic::TooManyGroups {
+                    max: usize::from(u16::MAX),
+                }),
+            }
+        }
+
+        diagnostics
+    }
+
     #[deprecated(note = "renamed to `duplicated_names`")]
     pub fn get_duplicated_names(&self) -> Vec<String> {
         self.duplicated_names()
@@ -168,13 +453,16 @@ impl People {
 
 impl From<Vec<Participant>> for People {
     fn from(value: Vec<Participant>) -> Self {
-        Self(value)
+        Self {
+            participants: value,
+            exclusions: Vec::new(),
+        }
     }
 }
 
 impl FromIterator<Participant> for People {
     fn from_iter<I: IntoIterator<Item = Participant>>(iter: I) -> Self {
-        Self(iter.into_iter().collect())
+        Self::from(iter.into_iter().collect::<Vec<_>>())
     }
 }
 
@@ -183,7 +471,7 @@ impl IntoIterator for People {
     type IntoIter = std::vec::IntoIter<Participant>;
 
     fn into_iter(self) -> Self::IntoIter {
-        self.0.into_iter()
+        self.participants.into_iter()
     }
 }
 
@@ -191,7 +479,7 @@ impl Deref for People {
     type Target = [Participant];
 
     fn deref(&self) -> &Self::Target {
-        &self.0
+        &self.participants
     }
 }
 
@@ -200,12 +488,12 @@ mod tests {
     use super::*;
 
     fn grouped_unchecked(people: People) -> GroupedPeople {
-        GroupedPeople(people)
+        GroupedPeople::new_unchecked(people).unwrap()
     }
 
     #[test]
     fn test_assign_from_path() {
-        let people = People(vec![
+        let people = People::from(vec![
             Participant::new("Father", 1),
             Participant::new("Mother", 2),
             Participant::new("Son", 1),
@@ -215,9 +503,57 @@ mod tests {
         assert_eq!(assigned.last().unwrap().assigned_person_name, "Father");
     }
 
+    #[test]
+    fn test_assign_from_path_carries_recipient_notes() {
+        let people = People::from(vec![
+            Participant {
+                wishlist: Some("Fishing gear".to_string()),
+                ..Participant::new("Father", 1)
+            },
+            Participant {
+                interests: Some("Gardening".to_string()),
+                ..Participant::new("Mother", 2)
+            },
+        ]);
+        let assigned = people.assign_from_path(&[0, 1]);
+        assert_eq!(
+            assigned[0].recipient_interests.as_deref(),
+            Some("Gardening")
+        );
+        assert_eq!(assigned[0].recipient_wishlist, None);
+        assert_eq!(
+            assigned[1].recipient_notes(),
+            vec![("Wishlist", "Fishing gear")]
+        );
+    }
+
+    #[test]
+    fn test_assign_from_cycles() {
+        let people = People::from(vec![
+            Participant::new("Father", 1),
+            Participant::new("Mother", 2),
+            Participant::new("Son", 1),
+            Participant::new("Daughter", 2),
+        ]);
+        let assigned = people.assign_from_cycles(&[vec![0, 1], vec![2, 3]]);
+        let pairs: Vec<(&str, &str)> = assigned
+            .iter()
+            .map(|p| (p.name.as_str(), p.assigned_person_name.as_str()))
+            .collect();
+        assert_eq!(
+            pairs,
+            vec![
+                ("Father", "Mother"),
+                ("Mother", "Father"),
+                ("Son", "Daughter"),
+                ("Daughter", "Son")
+            ]
+        );
+    }
+
     #[test]
     fn test_largest_group() {
-        let people = grouped_unchecked(People(vec![
+        let people = grouped_unchecked(People::from(vec![
             Participant::new("Father", 1),
             Participant::new("Mother", 1),
             Participant::new("Son", 2),
@@ -225,9 +561,95 @@ mod tests {
         assert_eq!(people.largest_group(), Group::new(1, 2));
     }
 
+    #[test]
+    fn test_named_groups_skip_numbers_in_use() {
+        let people = People::from(vec![
+            Participant::new("Father", u16::MAX),
+            Participant::named_group("Mother", "Hill family"),
+            Participant::new("Son", 1),
+            Participant::named_group("Daughter", "Brown family"),
+        ]);
+        assert_eq!(
+            people.groups().unwrap(),
+            vec![
+                Group::new(1, 1),
+                Group::labelled(2, "Hill family", 1),
+                Group::labelled(3, "Brown family", 1),
+                Group::new(u16::MAX, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_too_many_groups_is_an_error() {
+        let people: People = (0..=u16::MAX)
+            .map(|number| Participant::named_group(&format!("P{number}"), &format!("G{number}")))
+            .collect();
+        assert_eq!(
+            people.groups(),
+            Err(GiftCircleError::TooManyGroups {
+                max: usize::from(u16::MAX)
+            })
+        );
+    }
+
+    #[test]
+    fn test_groups_largest_first_without_ungrouped() {
+        let people = People::from(vec![
+            Participant::new("Father", 2),
+            Participant::named_group("Mother", "Hill family"),
+            Participant::new_no_group("Son"),
+            Participant::named_group("Daughter", "Hill family"),
+            Participant::new("Aunt", 1),
+        ]);
+        assert_eq!(
+            people.groups().unwrap(),
+            vec![
+                Group::labelled(3, "Hill family", 2),
+                Group::new(1, 1),
+                Group::new(2, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_largest_named_group() {
+        let people = grouped_unchecked(People::from(vec![
+            Participant::named_group("Father", "Hill family"),
+            Participant::named_group("Mother", "Hill family"),
+            Participant::new("Son", 2),
+            Participant::named_group("Daughter", "2"),
+            Participant::named_group("Aunt", "Hill family"),
+        ]));
+        assert_eq!(people.largest_group(), Group::labelled(3, "Hill family", 3));
+        assert_eq!(people.group_at(1), 3);
+        assert_eq!(people.group_at(3), 2);
+    }
+
+    #[test]
+    fn test_named_group_layout_error() {
+        let people = People::from(vec![
+            Participant::named_group("Father", "Hill family"),
+            Participant::named_group("Mother", "Hill family"),
+            Participant::named_group("Son", "Brown family"),
+        ]);
+        assert_eq!(
+            GroupedPeople::try_from(&people).unwrap_err(),
+            GiftCircleError::ImpossibleGroupLayout(GroupLayoutProblem {
+                group: "Hill family".to_string(),
+                size: 2,
+                total: 3,
+                max_group_size: 1,
+                excess: 1,
+                joiners_needed: 1,
+                suggested_split: vec![1, 1],
+            })
+        );
+    }
+
     #[test]
     fn test_largest_non_prev_group() {
-        let people = grouped_unchecked(People(vec![
+        let people = grouped_unchecked(People::from(vec![
             Participant::new("Father", 1),
             Participant::new("Mother", 1),
             Participant::new("Son", 2),
@@ -242,7 +664,7 @@ mod tests {
 
     #[test]
     fn test_has_possible_hamiltonian_path_true() {
-        let people = grouped_unchecked(People(vec![
+        let people = grouped_unchecked(People::from(vec![
             Participant::new("Father", 1),
             Participant::new("Mother", 1),
             Participant::new("Son", 2),
@@ -253,7 +675,7 @@ mod tests {
 
     #[test]
     fn test_has_possible_hamiltonian_path_false() {
-        let people = grouped_unchecked(People(vec![
+        let people = grouped_unchecked(People::from(vec![
             Participant::new("Father", 1),
             Participant::new("Mother", 1),
             Participant::new("Son", 1),
@@ -264,7 +686,7 @@ mod tests {
 
     #[test]
     fn test_has_empty_group_false() {
-        let people = People(vec![
+        let people = People::from(vec![
             Participant::new("Father", 1),
             Participant::new("Mother", 1),
             Participant::new("Son", 1),
@@ -275,7 +697,7 @@ mod tests {
 
     #[test]
     fn test_has_empty_group_true() {
-        let people = People(vec![
+        let people = People::from(vec![
             Participant::new("Father", 1),
             Participant::new("Mother", 1),
             Participant::new("Son", 1),
@@ -286,7 +708,7 @@ mod tests {
 
     #[test]
     fn test_duplicated_names_one() {
-        let people = People(vec![
+        let people = People::from(vec![
             Participant::new("Mother", 1),
             Participant::new("Mother", 1),
         ]);
@@ -295,16 +717,39 @@ mod tests {
 
     #[test]
     fn test_duplicated_names_none() {
-        let people = People(vec![
+        let people = People::from(vec![
             Participant::new("Mother", 1),
             Participant::new("Father", 1),
         ]);
         assert_eq!(people.duplicated_names().len(), 0);
     }
 
+    #[test]
+    fn test_excluded_index_pairs() {
+        let mut people = People::from(vec![
+            Participant::new("Mother", 1),
+            Participant::new("Father", 1),
+        ]);
+        people.exclude("Father", "Mother");
+        assert_eq!(
+            people.excluded_index_pairs().unwrap(),
+            HashSet::from([(1, 0)])
+        );
+    }
+
+    #[test]
+    fn test_excluded_index_pairs_unknown_name() {
+        let people = People::from(vec![Participant::new("Mother", 1)])
+            .with_exclusions([Exclusion::new("Mother", "Uncle")]);
+        assert_eq!(
+            people.excluded_index_pairs().unwrap_err(),
+            GiftCircleError::UnknownExclusionName("Uncle".to_string())
+        );
+    }
+
     #[test]
     fn test_first_and_last_groups_are_different_true() {
-        let people = grouped_unchecked(People(vec![
+        let people = grouped_unchecked(People::from(vec![
             Participant::new("Father", 1),
             Participant::new("Mother", 2),
             Participant::new("Son", 1),
@@ -315,7 +760,7 @@ mod tests {
 
     #[test]
     fn test_first_and_last_groups_are_different_false() {
-        let people = grouped_unchecked(People(vec![
+        let people = grouped_unchecked(People::from(vec![
             Participant::new("Father", 1),
             Participant::new("Mother", 2),
             Participant::new("Son", 1),
@@ -325,7 +770,7 @@ mod tests {
 
     #[test]
     fn test_has_no_consecutive_group_numbers_true() {
-        let people = grouped_unchecked(People(vec![
+        let people = grouped_unchecked(People::from(vec![
             Participant::new("Father", 1),
             Participant::new("Mother", 2),
             Participant::new("Son", 1),
@@ -336,7 +781,7 @@ mod tests {
 
     #[test]
     fn test_has_no_consecutive_group_numbers_false() {
-        let people = grouped_unchecked(People(vec![
+        let people = grouped_unchecked(People::from(vec![
             Participant::new("Father", 1),
             Participant::new("Mother", 2),
             Participant::new("Son", 2),
@@ -347,7 +792,7 @@ mod tests {
 
     #[test]
     fn test_is_valid_gift_circle() {
-        let people = grouped_unchecked(People(vec![
+        let people = grouped_unchecked(People::from(vec![
             Participant::new("Father", 1),
             Participant::new("Mother", 2),
             Participant::new("Son", 1),
@@ -358,7 +803,7 @@ mod tests {
 
     #[test]
     fn test_adjacent_groups_in_valid_circle_differ_including_wraparound() {
-        let people = grouped_unchecked(People(vec![
+        let people = grouped_unchecked(People::from(vec![
             Participant::new("Father", 1),
             Participant::new("Mother", 1),
             Participant::new("Son", 2),
@@ -367,14 +812,104 @@ mod tests {
         let path = vec![0, 2, 1, 3];
         assert!(people.is_valid_gift_circle(&path));
         for window in path.windows(2) {
-            assert_ne!(
-                GroupedPeople::group_at(&people, window[0]),
-                GroupedPeople::group_at(&people, window[1])
-            );
+            assert_ne!(people.group_at(window[0]), people.group_at(window[1]));
         }
         assert_ne!(
-            GroupedPeople::group_at(&people, *path.last().unwrap()),
-            GroupedPeople::group_at(&people, path[0])
+            people.group_at(*path.last().unwrap()),
+            people.group_at(path[0])
+        );
+    }
+
+    #[test]
+    fn test_validate_reports_every_problem() {
+        let mut duplicate = Participant::new("Father", 1);
+        duplicate.line = Some(5);
+        let mut bad_email = Participant::new("Mother", 1);
+        bad_email.email_address = Some("mother.example.com".to_string());
+        let mut people = People::from(vec![
+            Participant {
+                line: Some(2),
+                ..Participant::new("Father", 1)
+            },
+            bad_email,
+            Participant::new_no_group("Son"),
+            Participant::new("   ", 1),
+            duplicate,
+        ]);
+        people.exclude("Father", "Grandma");
+
+        let diagnostics = people.validate(GiftMode::Grouped);
+        let at = |entry, line| Location { entry, line };
+        assert_eq!(
+            diagnostics,
+            vec![
+                Diagnostic::MalformedEmail {
+                    name: "Mother".to_string(),
+                    email_address: "mother.example.com".to_string(),
+                    at: at(2, None),
+                },
+                Diagnostic::MissingGroup {
+                    name: "Son".to_string(),
+                    at: at(3, None),
+                },
+                Diagnostic::WhitespaceName {
+                    name: "   ".to_string(),
+                    at: at(4, None),
+                },
+                Diagnostic::DuplicateName {
+                    name: "Father".to_string(),
+                    at: vec![at(1, Some(2)), at(5, Some(5))],
+                },
+                Diagnostic::UnknownExclusionName {
+                    name: "Grandma".to_string(),
+                },
+            ]
+        );
+        assert_eq!(
+            diagnostics[3].to_string(),
+            "line 2, line 5: name \"Father\" is used more than once"
+        );
+    }
+
+    #[test]
+    fn test_validate_explains_impossible_layout() {
+        let people = People::from(vec![
+            Participant::new("Father", 1),
+            Participant::new("Mother", 1),
+            Participant::new("Son", 1),
+            Participant::new("Daughter", 2),
+        ]);
+        assert_eq!(
+            people.validate(GiftMode::Grouped),
+            vec![Diagnostic::ImpossibleGroupLayout(GroupLayoutProblem::new(
+                "1", 3, 4
+            ))]
+        );
+        assert!(people.validate(GiftMode::Plain).is_empty());
+    }
+
+    #[test]
+    fn test_layout_problem_suggests_fixes() {
+        let people = grouped_unchecked(People::from(vec![
+            Participant::named_group("Father", "Hill family"),
+            Participant::named_group("Mother", "Hill family"),
+            Participant::named_group("Son", "Hill family"),
+            Participant::named_group("Daughter", "Hill family"),
+            Participant::named_group("Grandpa", "Hill family"),
+            Participant::named_group("Aunt", "Brown family"),
+            Participant::named_group("Uncle", "Brown family"),
+        ]));
+        let problem = people.layout_problem();
+        assert_eq!(problem.max_group_size, 3);
+        assert_eq!(problem.excess, 2);
+        assert_eq!(problem.joiners_needed, 3);
+        assert_eq!(problem.suggested_split, vec![3, 2]);
+        assert_eq!(
+            people.impossible_layout_error().to_string(),
+            "Sorry, no possible gift circle with this set of groups; \
+             group \"Hill family\" has 5 of 7 participants but can hold at most 3, \
+             because everyone in it must give to and receive from someone outside it. \
+             Add 3 more participants from other groups, or split \"Hill family\" into groups of 3 and 2."
         );
     }
 }
@@ -1,7 +1,7 @@
 use serde::{Deserialize, Serialize};
 
 /// A participant read from the input CSV before gift assignments are generated.
-#[derive(Clone, PartialEq, Eq, Debug, Default, Deserialize)]
+#[derive(Clone, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
 pub struct Participant {
     /// Unique participant name.
     pub name: String,
@@ -9,10 +9,43 @@ pub struct Participant {
     pub email_address: Option<String>,
     /// Family group number when `--use-groups` is enabled.
     pub group_number: Option<u16>,
+    /// Family group name when `--use-groups` is enabled; takes precedence over `group_number`.
+    pub group: Option<String>,
+    /// Passphrase used to encrypt this participant's sealed assignment; never written out.
+    #[serde(default, skip_serializing)]
+    pub passphrase: Option<String>,
+    /// age X25519 public key (`age1...`) used to encrypt this participant's sealed assignment.
+    pub public_key: Option<String>,
+    /// Gift ideas or a link to a wishlist, shown to whoever gives to this participant.
+    pub wishlist: Option<String>,
+    /// Clothing or other sizes, shown to whoever gives to this participant.
+    pub sizes: Option<String>,
+    /// Hobbies and interests, shown to whoever gives to this participant.
+    pub interests: Option<String>,
+    /// Things this participant would rather not receive.
+    pub do_not_buy: Option<String>,
+    /// Line of the input CSV this participant was read from, used in diagnostics.
+    #[serde(skip)]
+    pub line: Option<u64>,
+}
+
+fn group_label(group: Option<&str>, group_number: Option<u16>) -> Option<String> {
+    group
+        .map(str::trim)
+        .filter(|label| !label.is_empty())
+        .map(str::to_string)
+        .or_else(|| group_number.map(|number| number.to_string()))
+}
+
+impl Participant {
+    /// The participant's group name, or its number written out when only that is given.
+    pub fn group_label(&self) -> Option<String> {
+        group_label(self.group.as_deref(), self.group_number)
+    }
 }
 
 /// A participant row written to the output CSV with an assigned recipient.
-#[derive(Clone, PartialEq, Eq, Debug, Serialize)]
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
 pub struct Person {
     /// Unique participant name.
     pub name: String,
@@ -22,17 +55,62 @@ pub struct Person {
     pub group_number: Option<u16>,
     /// Name of the person this participant gives a gift to.
     pub assigned_person_name: String,
+    /// Family group name from the input CSV, if any.
+    #[serde(default)]
+    pub group: Option<String>,
+    /// The recipient's wishlist.
+    #[serde(default)]
+    pub recipient_wishlist: Option<String>,
+    /// The recipient's sizes.
+    #[serde(default)]
+    pub recipient_sizes: Option<String>,
+    /// The recipient's interests.
+    #[serde(default)]
+    pub recipient_interests: Option<String>,
+    /// Things the recipient would rather not receive.
+    #[serde(default)]
+    pub recipient_do_not_buy: Option<String>,
 }
 
 impl Person {
-    pub(crate) fn from_assignment(participant: &Participant, assigned_person_name: String) -> Self {
+    /// The output row for `giver`, carrying the recipient's gift notes rather than the
+    /// giver's own.
+    pub(crate) fn from_assignment(giver: &Participant, recipient: &Participant) -> Self {
         Self {
-            name: participant.name.clone(),
-            email_address: participant.email_address.clone(),
-            group_number: participant.group_number,
-            assigned_person_name,
+            name: giver.name.clone(),
+            email_address: giver.email_address.clone(),
+            group_number: giver.group_number,
+            assigned_person_name: recipient.name.clone(),
+            group: giver.group.clone(),
+            recipient_wishlist: recipient.wishlist.clone(),
+            recipient_sizes: recipient.sizes.clone(),
+            recipient_interests: recipient.interests.clone(),
+            recipient_do_not_buy: recipient.do_not_buy.clone(),
         }
     }
+
+    /// The giver's group name, or its number written out when only that is given.
+    pub fn group_label(&self) -> Option<String> {
+        group_label(self.group.as_deref(), self.group_number)
+    }
+
+    /// The recipient's gift notes that are filled in, each with a label.
+    pub fn recipient_notes(&self) -> Vec<(&'static str, &str)> {
+        [
+            ("Wishlist", &self.recipient_wishlist),
+            ("Sizes", &self.recipient_sizes),
+            ("Interests", &self.recipient_interests),
+            ("Please do not buy", &self.recipient_do_not_buy),
+        ]
+        .into_iter()
+        .filter_map(|(label, note)| {
+            note.as_deref()
+                .map(str::trim)
+                .filter(|note| !note.is_empty())
+                .map(|note| (label, note))
+        })
+        .collect()
+    }
 }
 
 #[cfg(test)]
@@ -45,6 +123,14 @@ impl Participant {
         }
     }
 
+    pub fn named_group(name: &str, group: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            group: Some(group.to_string()),
+            ..Default::default()
+        }
+    }
+
     pub fn new_no_group(name: &str) -> Self {
         Self {
             name: name.to_string(),
@@ -0,0 +1,392 @@
+//! Repair an earlier draw after people join or drop out, keeping as many of the
+//! original assignments as possible so few people need to be told anything new.
+
+use std::collections::HashMap;
+
+use rand::prelude::{Rng, RngExt, SliceRandom};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{GiftCircleError, Result};
+use crate::gift_circle::{find_cycles, validate_people, GenerateOptions, GiftCircleOutput, Rules};
+use crate::mode::GiftMode;
+use crate::people::People;
+use crate::solver;
+
+/// A repaired draw and who needs to hear about it.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RepairOutput {
+    /// Assignments for everyone now taking part.
+    pub output: GiftCircleOutput,
+    /// Givers with a new recipient, including everyone who joined; nobody else needs
+    /// to be told anything.
+    pub notify: Vec<String>,
+    /// Givers from the previous draw who are no longer taking part.
+    pub removed: Vec<String>,
+}
+
+/// Ways of dropping kept pairs tried before dropping them at random instead, which keeps
+/// a repair quick when many pairs would have to go.
+const MAX_EXACT_TRIES: usize = 1_000;
+
+/// How far each attempt to join the chains searches before that way of keeping pairs is
+/// given up on.
+const JOIN_STEPS: usize = 1_000;
+
+/// Rebuild `previous` for the participants in `people`, changing as few existing
+/// giver/recipient pairs as possible while following the groups and exclusions of
+/// `mode` and `people`, just like [`crate::generate_with_rng`].
+///
+/// Previous pairs that are still allowed are kept as chains, and the chains and newcomers
+/// are joined into a circle. Pairs are only given up when the chains cannot be joined:
+/// every way of dropping one pair is tried, then every way of dropping two, and so on,
+/// so the fewest pairs are given up. After a thousand ways, pairs are dropped one at a
+/// time at random until the rest can be joined instead.
+///
+/// # Errors
+///
+/// Returns [`GiftCircleError`] when the new participants fail validation, when no circle
+/// satisfies the rules at all, or when `previous` has several gifts per person.
+pub fn repair(
+    previous: &GiftCircleOutput,
+    people: &People,
+    mode: GiftMode,
+    rng: &mut impl Rng,
+) -> Result<RepairOutput> {
+    if previous.gifts_per_person > 1 {
+        return Err(GiftCircleError::UnsupportedRepair {
+            gifts_per_person: previous.gifts_per_person,
+        });
+    }
+    let validated = validate_people(people, mode)?;
+    let rules = Rules::new(people, &validated)?;
+
+    let index_of: HashMap<&str, usize> = people
+        .iter()
+        .enumerate()
+        .map(|(index, participant)| (participant.name.as_str(), index))
+        .collect();
+    let mut kept: Vec<(usize, usize)> = previous
+        .people
+        .iter()
+        .filter_map(|person| {
+            let giver = *index_of.get(person.name.as_str())?;
+            let recipient = *index_of.get(person.assigned_person_name.as_str())?;
+            rules.allows(giver, recipient).then_some((giver, recipient))
+        })
+        .collect();
+    kept.shuffle(rng);
+
+    // Without any kept pairs the search below is only worth running if a draw from
+    // scratch is possible at all.
+    let (from_scratch, _) = find_cycles(
+        people,
+        &validated,
+        &rules,
+        &GenerateOptions::from(mode),
+        rng,
+    )?;
+
+    let mut found = None;
+    let mut tries = 0;
+    'dropping: for count in 0..=kept.len() {
+        let mut dropped: Vec<usize> = (0..count).collect();
+        loop {
+            tries += 1;
+            if tries > MAX_EXACT_TRIES {
+                break 'dropping;
+            }
+            let fewer: Vec<(usize, usize)> = kept
+                .iter()
+                .enumerate()
+                .filter(|(index, _)| !dropped.contains(index))
+                .map(|(_, &pair)| pair)
+                .collect();
+            if let Some(cycles) = join_chains(people.len(), &fewer, &rules, mode, rng) {
+                found = Some(cycles);
+                break 'dropping;
+            }
+            if !next_combination(&mut dropped, kept.len()) {
+                break;
+            }
+        }
+    }
+    while found.is_none() && !kept.is_empty() {
+        let dropped = rng.random_range(0..kept.len());
+        kept.swap_remove(dropped);
+        found = join_chains(people.len(), &kept, &rules, mode, rng);
+    }
+    let cycles = found.unwrap_or(from_scratch);
+
+    let assigned = people.assign_from_cycles(&cycles);
+    let notify = assigned
+        .iter()
+        .filter(|person| {
+            !previous.people.iter().any(|old| {
+                old.name == person.name && old.assigned_person_name == person.assigned_person_name
+            })
+        })
+        .map(|person| person.name.clone())
+        .collect();
+    let mut removed: Vec<String> = Vec::new();
+    for old in &previous.people {
+        if !index_of.contains_key(old.name.as_str()) && !removed.contains(&old.name) {
+            removed.push(old.name.clone());
+        }
+    }
+
+    Ok(RepairOutput {
+        output: GiftCircleOutput {
+            people: assigned,
+            attempts: 1,
+            used_groups: mode.uses_groups(),
+            relaxed_history: Vec::new(),
+            gifts_per_person: 1,
+            metadata: None,
+            event: previous.event.clone(),
+        },
+        notify,
+        removed,
+    })
+}
+
+/// Move `chosen`, a sorted choice of positions in `0..len`, on to the next choice of as
+/// many positions, or return `false` when it was the last one.
+fn next_combination(chosen: &mut [usize], len: usize) -> bool {
+    let count = chosen.len();
+    for position in (0..count).rev() {
+        if chosen[position] < len - count + position {
+            chosen[position] += 1;
+            for next in position + 1..count {
+                chosen[next] = chosen[next - 1] + 1;
+            }
+            return true;
+        }
+    }
+    false
+}
+
+/// Join the chains formed by the `kept` pairs into assignments for everyone, keeping
+/// every kept pair, or `None` when that is impossible or no way was found quickly.
+///
+/// Closed loops of kept pairs stay as they are when `mode` allows several loops;
+/// otherwise a closed loop is only acceptable when it already includes everyone.
+fn join_chains(
+    len: usize,
+    kept: &[(usize, usize)],
+    rules: &Rules,
+    mode: GiftMode,
+    rng: &mut impl Rng,
+) -> Option<Vec<Vec<usize>>> {
+    let mut next = vec![None; len];
+    let mut has_giver = vec![false; len];
+    for &(giver, recipient) in kept {
+        next[giver] = Some(recipient);
+        has_giver[recipient] = true;
+    }
+
+    let mut seen = vec![false; len];
+    let mut chains = Vec::new();
+    for head in (0..len).filter(|&index| !has_giver[index]) {
+        let mut chain = vec![head];
+        seen[head] = true;
+        let mut current = head;
+        while let Some(recipient) = next[current] {
+            seen[recipient] = true;
+            chain.push(recipient);
+            current = recipient;
+        }
+        chains.push(chain);
+    }
+    let mut loops = Vec::new();
+    for start in 0..len {
+        let mut current = start;
+        let mut closed = Vec::new();
+        while !seen[current] {
+            seen[current] = true;
+            closed.push(current);
+            current = next[current]?;
+        }
+        if !closed.is_empty() {
+            loops.push(closed);
+        }
+    }
+
+    match mode.min_cycle_length() {
+        None if !loops.is_empty() => {
+            return (chains.is_empty() && loops.len() == 1).then_some(loops);
+        }
+        None => {}
+        Some(min_cycle_length) => {
+            if loops.iter().any(|closed| closed.len() < min_cycle_length) {
+                return None;
+            }
+            if chains.is_empty() {
+                return Some(loops);
+            }
+            if chains.iter().map(Vec::len).sum::<usize>() < min_cycle_length {
+                return None;
+            }
+        }
+    }
+
+    let tail = |chain: usize| chains[chain][chains[chain].len() - 1];
+    let order = if chains.len() == 1 {
+        (chains[0].len() > 1 && rules.allows(tail(0), chains[0][0])).then(|| vec![0])?
+    } else {
+        solver::find_circle_within(
+            chains.len(),
+            |from, to| from != to && rules.allows(tail(from), chains[to][0]),
+            JOIN_STEPS,
+            rng,
+        )?
+    };
+    loops.push(
+        order
+            .into_iter()
+            .flat_map(|chain| chains[chain].iter().copied())
+            .collect(),
+    );
+    Some(loops)
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::expect_used)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::gift_circle::generate_with_rng;
+    use crate::person::Participant;
+    use crate::verify::verify;
+
+    fn family() -> Vec<Participant> {
+        vec![
+            Participant::new("Father", 1),
+            Participant::new("Mother", 1),
+            Participant::new("Son", 2),
+            Participant::new("Daughter", 2),
+            Participant::new("Grandpa", 3),
+            Participant::new("Grandma", 3),
+        ]
+    }
+
+    #[test]
+    fn dropout_only_changes_their_giver() {
+        for seed in 0..20 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let previous =
+                generate_with_rng(&People::from(family()), GiftMode::Grouped, &mut rng).unwrap();
+            let people: People = family()
+                .into_iter()
+                .filter(|participant| participant.name != "Son")
+                .collect();
+
+            let repaired = repair(&previous, &people, GiftMode::Grouped, &mut rng).unwrap();
+            assert!(verify(&people, &repaired.output.people, GiftMode::Grouped, 1).is_empty());
+            assert_eq!(repaired.removed, vec!["Son".to_string()]);
+            let sons_giver = previous
+                .people
+                .iter()
+                .find(|person| person.assigned_person_name == "Son")
+                .unwrap();
+            let sons_recipient = &previous
+                .people
+                .iter()
+                .find(|person| person.name == "Son")
+                .unwrap()
+                .assigned_person_name;
+            assert!(repaired.notify.contains(&sons_giver.name));
+            if sons_giver.group_number
+                != people
+                    .iter()
+                    .find(|p| &p.name == sons_recipient)
+                    .unwrap()
+                    .group_number
+            {
+                assert_eq!(repaired.notify, vec![sons_giver.name.clone()]);
+            } else {
+                assert_eq!(repaired.notify.len(), 3, "{:?}", repaired.notify);
+            }
+        }
+    }
+
+    #[test]
+    fn joiner_breaks_one_pair() {
+        for seed in 0..20 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let previous =
+                generate_with_rng(&People::from(family()), GiftMode::Grouped, &mut rng).unwrap();
+            let mut participants = family();
+            participants.push(Participant::new("Cousin", 4));
+            let people = People::from(participants);
+
+            let repaired = repair(&previous, &people, GiftMode::Grouped, &mut rng).unwrap();
+            assert!(verify(&people, &repaired.output.people, GiftMode::Grouped, 1).is_empty());
+            assert_eq!(repaired.notify.len(), 2, "{:?}", repaired.notify);
+            assert!(repaired.notify.contains(&"Cousin".to_string()));
+            assert!(repaired.removed.is_empty());
+        }
+    }
+
+    #[test]
+    fn unchanged_participants_keep_every_pair() {
+        let mut rng = StdRng::seed_from_u64(5);
+        let people = People::from(family());
+        let previous = generate_with_rng(&people, GiftMode::Grouped, &mut rng).unwrap();
+        let repaired = repair(&previous, &people, GiftMode::Grouped, &mut rng).unwrap();
+        assert!(repaired.notify.is_empty());
+    }
+
+    #[test]
+    fn drops_the_fewest_pairs_needed() {
+        // A plain draw from before the family used groups. Only Mother -> Father and
+        // Father -> Grandpa break the groups, but the five pairs left cannot all be kept
+        // either: two more have to go, so four givers hear about a change.
+        let people = People::from(vec![
+            Participant::new("Son", 2),
+            Participant::new("Father", 1),
+            Participant::new("Mother", 1),
+            Participant::new("Grandpa", 1),
+            Participant::new("Aunt", 3),
+            Participant::new("Daughter", 2),
+            Participant::new("Uncle", 3),
+        ]);
+        let previous = GiftCircleOutput {
+            people: people.assign_from_cycles(&[vec![3, 0, 6, 5, 4, 2, 1]]),
+            attempts: 1,
+            used_groups: false,
+            relaxed_history: Vec::new(),
+            gifts_per_person: 1,
+            metadata: None,
+            event: None,
+        };
+        for seed in 0..20 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let repaired = repair(&previous, &people, GiftMode::Grouped, &mut rng).unwrap();
+            assert!(verify(&people, &repaired.output.people, GiftMode::Grouped, 1).is_empty());
+            assert_eq!(repaired.notify.len(), 4, "{:?}", repaired.notify);
+        }
+    }
+
+    #[test]
+    fn impossible_repair_fails_quickly() {
+        let participants: Vec<Participant> = (1..=22)
+            .map(|number| Participant::new_no_group(&format!("P{number}")))
+            .collect();
+        let mut rng = StdRng::seed_from_u64(3);
+        let previous = generate_with_rng(
+            &People::from(participants.clone()),
+            GiftMode::Plain,
+            &mut rng,
+        )
+        .unwrap();
+        let mut people = People::from(participants);
+        for number in 2..=22 {
+            people.exclude("P1", format!("P{number}"));
+        }
+
+        let err = repair(&previous, &people, GiftMode::Plain, &mut rng).unwrap_err();
+        assert_eq!(err, GiftCircleError::ImpossibleExclusions);
+    }
+}
@@ -0,0 +1,253 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use serde::Serialize;
+
+use crate::encryption::Lock;
+use crate::event::Event;
+use crate::format::{self, Format};
+use crate::lookup::GiverCode;
+use crate::people::People;
+use crate::person::Person;
+
+/// One giver's assignments, to be written to a file only they should open.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SealedAssignment {
+    /// File name stem derived from the giver's name, unique within one draw.
+    pub slug: String,
+    /// The giver's output rows; several when giving more than one gift.
+    pub people: Vec<Person>,
+}
+
+/// Turn a name into a lowercase, hyphen-separated file name stem.
+pub fn slugify(name: &str) -> String {
+    let mut slug = String::with_capacity(name.len());
+    for character in name.chars() {
+        if character.is_alphanumeric() {
+            slug.extend(character.to_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    while slug.ends_with('-') {
+        slug.pop();
+    }
+    if slug.is_empty() {
+        slug.push_str("participant");
+    }
+    slug
+}
+
+/// Split assignment rows by giver, in the order givers first appear.
+pub fn seal(people: &[Person]) -> Vec<SealedAssignment> {
+    let mut sealed: Vec<SealedAssignment> = Vec::new();
+    let mut slugs = HashSet::new();
+
+    for person in people {
+        if let Some(existing) = sealed
+            .iter_mut()
+            .find(|sealed| sealed.people[0].name == person.name)
+        {
+            existing.people.push(person.clone());
+            continue;
+        }
+
+        let base = slugify(&person.name);
+        let mut slug = base.clone();
+        let mut suffix = 2;
+        while !slugs.insert(slug.clone()) {
+            slug = format!("{base}-{suffix}");
+            suffix += 1;
+        }
+        sealed.push(SealedAssignment {
+            slug,
+            people: vec![person.clone()],
+        });
+    }
+
+    sealed
+}
+
+fn extension(format: Format) -> &'static str {
+    match format {
+        Format::Csv => "csv",
+        Format::Json => "json",
+    }
+}
+
+fn write_files(dir: &Path, files: Vec<(String, Vec<u8>)>) -> Result<Vec<PathBuf>> {
+    fs::create_dir_all(dir)
+        .with_context(|| format!("Failed to create output directory {}", dir.display()))?;
+
+    files
+        .into_iter()
+        .map(|(file_name, contents)| {
+            let path = dir.join(file_name);
+            fs::write(&path, contents)
+                .with_context(|| format!("Failed to write {}", path.display()))?;
+            Ok(path)
+        })
+        .collect()
+}
+
+/// One giver's file: their rows, plus the event as they see it when there is one, as `#`
+/// lines after CSV rows or as a JSON object holding `people` and `event`.
+fn contents(sealed: &SealedAssignment, event: Option<&Event>, format: Format) -> Result<Vec<u8>> {
+    let mut contents = Vec::new();
+    let Some(event) = event else {
+        format::write_people(&mut contents, &sealed.people, format)?;
+        return Ok(contents);
+    };
+    let event = event.for_giver(sealed.people[0].group_label().as_deref());
+    match format {
+        Format::Csv => {
+            format::write_people(&mut contents, &sealed.people, format)?;
+            format::write_event_trailer(&mut contents, &event)?;
+        }
+        Format::Json => {
+            #[derive(Serialize)]
+            struct SealedFile<'a> {
+                people: &'a [Person],
+                event: Event,
+            }
+            let file = SealedFile {
+                people: &sealed.people,
+                event,
+            };
+            serde_json::to_writer_pretty(&mut contents, &file)?;
+            contents.push(b'\n');
+        }
+    }
+    Ok(contents)
+}
+
+/// Write one file per giver into `dir`, each holding only that giver's assignments and
+/// the event details.
+pub fn write_sealed_files(
+    dir: &Path,
+    people: &[Person],
+    event: Option<&Event>,
+    format: Format,
+) -> Result<Vec<PathBuf>> {
+    let files = seal(people)
+        .into_iter()
+        .map(|sealed| {
+            let contents = contents(&sealed, event, format)?;
+            Ok((format!("{}.{}", sealed.slug, extension(format)), contents))
+        })
+        .collect::<Result<_>>()?;
+    write_files(dir, files)
+}
+
+fn lock_for(participants: &People, giver: &str) -> Result<Lock> {
+    let participant = participants
+        .iter()
+        .find(|participant| participant.name == giver)
+        .ok_or_else(|| anyhow!("No participant named {giver}"))?;
+    Lock::for_participant(participant)
+}
+
+/// Like [`write_sealed_files`], but encrypt each file with the giver's passphrase or
+/// public key from `participants`, adding an `.age` extension.
+///
+/// Nothing is written unless every giver can be encrypted for.
+pub fn write_encrypted_sealed_files(
+    dir: &Path,
+    people: &[Person],
+    participants: &People,
+    event: Option<&Event>,
+    format: Format,
+) -> Result<Vec<PathBuf>> {
+    let files = seal(people)
+        .into_iter()
+        .map(|sealed| {
+            let lock = lock_for(participants, &sealed.people[0].name)?;
+            let plaintext = contents(&sealed, event, format)?;
+            let file_name = format!("{}.{}.age", sealed.slug, extension(format));
+            Ok((file_name, lock.encrypt(&plaintext)?.into_bytes()))
+        })
+        .collect::<Result<_>>()?;
+    write_files(dir, files)
+}
+
+/// Write one file per giver into `dir` holding only their lookup code, encrypted with
+/// their passphrase or public key from `participants` when given.
+pub fn write_code_files(
+    dir: &Path,
+    codes: &[GiverCode],
+    participants: Option<&People>,
+    format: Format,
+) -> Result<Vec<PathBuf>> {
+    let files = codes
+        .iter()
+        .map(|code| {
+            let mut contents = Vec::new();
+            match format {
+                Format::Csv => {
+                    let mut writer = csv::Writer::from_writer(&mut contents);
+                    writer.serialize(code)?;
+                    writer.flush()?;
+                }
+                Format::Json => {
+                    serde_json::to_writer_pretty(&mut contents, code)?;
+                    contents.push(b'\n');
+                }
+            }
+            let file_name = format!("{}.{}", code.slug, extension(format));
+            match participants {
+                Some(participants) => {
+                    let lock = lock_for(participants, &code.name)?;
+                    Ok((
+                        format!("{file_name}.age"),
+                        lock.encrypt(&contents)?.into_bytes(),
+                    ))
+                }
+                None => Ok((file_name, contents)),
+            }
+        })
+        .collect::<Result<_>>()?;
+    write_files(dir, files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::person::Participant;
+
+    #[test]
+    fn slugify_names() {
+        assert_eq!(slugify("Joe Hill"), "joe-hill");
+        assert_eq!(slugify("  Mary-Jo  O'Neil! "), "mary-jo-o-neil");
+        assert_eq!(slugify("Zoë"), "zoë");
+        assert_eq!(slugify("???"), "participant");
+    }
+
+    #[test]
+    fn seal_groups_rows_by_giver_with_unique_slugs() {
+        let rows = vec![
+            Person::from_assignment(
+                &Participant::new_no_group("Joe Hill"),
+                &Participant::new_no_group("Ann"),
+            ),
+            Person::from_assignment(
+                &Participant::new_no_group("joe hill"),
+                &Participant::new_no_group("Joe Hill"),
+            ),
+            Person::from_assignment(
+                &Participant::new_no_group("Ann"),
+                &Participant::new_no_group("joe hill"),
+            ),
+            Person::from_assignment(
+                &Participant::new_no_group("Joe Hill"),
+                &Participant::new_no_group("joe hill"),
+            ),
+        ];
+        let sealed = seal(&rows);
+        let slugs: Vec<&str> = sealed.iter().map(|s| s.slug.as_str()).collect();
+        assert_eq!(slugs, vec!["joe-hill", "joe-hill-2", "ann"]);
+        assert_eq!(sealed[0].people.len(), 2);
+        assert!(sealed[0].people.iter().all(|p| p.name == "Joe Hill"));
+    }
+}
@@ -0,0 +1,524 @@
+//! A small local web server for running draws without the command line.
+//!
+//! Draws are kept in memory only and are lost when the server stops. Anyone holding a
+//! draw's id can see every assignment, so the id is meant for the organiser; each giver
+//! gets their own private link that shows only who they give to.
+
+use std::collections::HashMap;
+use std::io::Read;
+
+use anyhow::{anyhow, Context, Result};
+use rand::RngExt;
+use serde::{Deserialize, Serialize};
+
+use crate::event::Event;
+use crate::exclusion::Exclusion;
+use crate::format::{self, Format};
+use crate::gift_circle::{generate_with_seed, GenerateOptions, GiftCircleOutput};
+use crate::mode::{GiftMode, Sampling};
+use crate::people::People;
+use crate::person::Participant;
+use crate::sealed;
+
+/// Largest request body accepted, in bytes.
+const MAX_BODY_BYTES: u64 = 1024 * 1024;
+
+/// Body of `POST /draws`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DrawRequest {
+    /// Participants as the text of a participants CSV file.
+    pub participants_csv: Option<String>,
+    /// Participants as JSON objects, used when `participants_csv` is not given.
+    #[serde(default)]
+    pub participants: Vec<Participant>,
+    #[serde(default)]
+    pub mode: GiftMode,
+    /// How to pick among valid assignments.
+    #[serde(default)]
+    pub sampling: Sampling,
+    /// Giver,recipient pairs that must never be assigned.
+    #[serde(default)]
+    pub exclusions: Vec<Exclusion>,
+    pub gifts_per_person: Option<usize>,
+    /// Seed for the draw; a random one is chosen when omitted.
+    pub seed: Option<u64>,
+    /// Budget, date and location shown to every giver.
+    #[serde(default)]
+    pub event: Event,
+}
+
+/// A giver's private link to their own assignment.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GiverLink {
+    pub name: String,
+    /// Path of the page showing only this giver's recipient.
+    pub url: String,
+}
+
+/// Body returned by `POST /draws`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DrawCreated {
+    pub id: String,
+    /// Path of the full results as JSON; add `?format=csv` for CSV.
+    pub url: String,
+    pub links: Vec<GiverLink>,
+}
+
+/// A response to send back, independent of the HTTP library.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Response {
+    pub status: u16,
+    pub content_type: &'static str,
+    /// Suggested file name when the body is meant to be downloaded.
+    pub file_name: Option<String>,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    fn new(status: u16, content_type: &'static str, body: impl Into<Vec<u8>>) -> Self {
+        Self {
+            status,
+            content_type,
+            file_name: None,
+            body: body.into(),
+        }
+    }
+
+    fn json(status: u16, value: &impl Serialize) -> Self {
+        match serde_json::to_vec_pretty(value) {
+            Ok(body) => Self::new(status, "application/json", body),
+            Err(err) => Self::error(500, &err.to_string()),
+        }
+    }
+
+    fn error(status: u16, message: &str) -> Self {
+        #[derive(Serialize)]
+        struct Error<'a> {
+            error: &'a str,
+        }
+        let body = serde_json::to_vec(&Error { error: message }).unwrap_or_default();
+        Self::new(status, "application/json", body)
+    }
+
+    fn not_found() -> Self {
+        Self::error(404, "not found")
+    }
+}
+
+/// Draws made since the server started, with the private links handed out for them.
+#[derive(Debug, Default)]
+pub struct Draws {
+    draws: HashMap<String, GiftCircleOutput>,
+    /// Private link token to the draw id and giver name it reveals.
+    reveals: HashMap<String, (String, String)>,
+}
+
+fn random_token() -> String {
+    format!("{:032x}", rand::rng().random::<u128>())
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for character in text.chars() {
+        match character {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(character),
+        }
+    }
+    escaped
+}
+
+impl DrawRequest {
+    fn participants(&self) -> Result<Vec<Participant>> {
+        let Some(text) = &self.participants_csv else {
+            return Ok(self.participants.clone());
+        };
+        let mut rdr = csv::Reader::from_reader(text.as_bytes());
+        rdr.deserialize()
+            .collect::<Result<Vec<Participant>, _>>()
+            .context("Failed to read participants CSV")
+    }
+}
+
+impl Draws {
+    /// Answer one request. `target` is the request path, optionally with a query string.
+    pub fn handle(&mut self, method: &str, target: &str, body: &[u8]) -> Response {
+        let (path, query) = target.split_once('?').unwrap_or((target, ""));
+        let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+
+        match (method, segments.as_slice()) {
+            ("GET", []) => Response::new(200, "text/html; charset=utf-8", INDEX_HTML),
+            ("POST", ["draws"]) => match self.create(body) {
+                Ok(created) => Response::json(201, &created),
+                Err(err) => Response::error(400, &format!("{err:#}")),
+            },
+            ("GET", ["draws", id]) => self.results(id, query),
+            ("GET", ["reveal", token]) => self.reveal(token),
+            (_, [] | ["draws"] | ["draws", _] | ["reveal", _]) => {
+                Response::error(405, "method not allowed")
+            }
+            _ => Response::not_found(),
+        }
+    }
+
+    fn create(&mut self, body: &[u8]) -> Result<DrawCreated> {
+        let request: DrawRequest =
+            serde_json::from_slice(body).context("Failed to read draw request")?;
+        let people = People::from(request.participants()?).with_exclusions(request.exclusions);
+        let options = GenerateOptions {
+            mode: request.mode,
+            gifts_per_person: request.gifts_per_person.unwrap_or(1),
+            sampling: request.sampling,
+            ..GenerateOptions::default()
+        };
+        let seed = request.seed.unwrap_or_else(|| rand::rng().random());
+        let mut output = generate_with_seed(&people, &options, seed)?;
+        if !request.event.is_empty() {
+            output.event = Some(request.event);
+        }
+
+        let id = random_token();
+        let links = sealed::seal(&output.people)
+            .into_iter()
+            .map(|assignment| {
+                let token = random_token();
+                let name = assignment.people[0].name.clone();
+                self.reveals
+                    .insert(token.clone(), (id.clone(), name.clone()));
+                GiverLink {
+                    name,
+                    url: format!("/reveal/{token}"),
+                }
+            })
+            .collect();
+        self.draws.insert(id.clone(), output);
+
+        Ok(DrawCreated {
+            url: format!("/draws/{id}"),
+            id,
+            links,
+        })
+    }
+
+    fn results(&self, id: &str, query: &str) -> Response {
+        let Some(output) = self.draws.get(id) else {
+            return Response::not_found();
+        };
+        let format = match query
+            .split('&')
+            .find_map(|pair| pair.strip_prefix("format="))
+        {
+            None | Some("json") => Format::Json,
+            Some("csv") => Format::Csv,
+            Some(other) => return Response::error(400, &format!("unknown format {other:?}")),
+        };
+
+        let mut body = Vec::new();
+        if let Err(err) = format::write_output(&mut body, output, format) {
+            return Response::error(500, &format!("{err:#}"));
+        }
+        match format {
+            Format::Json => Response::new(200, "application/json", body),
+            Format::Csv => Response {
+                file_name: Some("assignments.csv".to_string()),
+                ..Response::new(200, "text/csv; charset=utf-8", body)
+            },
+        }
+    }
+
+    fn reveal(&self, token: &str) -> Response {
+        let Some((id, giver)) = self.reveals.get(token) else {
+            return Response::not_found();
+        };
+        let Some(output) = self.draws.get(id) else {
+            return Response::not_found();
+        };
+        let assignments: Vec<_> = output
+            .people
+            .iter()
+            .filter(|person| &person.name == giver)
+            .collect();
+        let recipients: Vec<String> = assignments
+            .iter()
+            .map(|person| {
+                format!(
+                    "<strong>{}</strong>",
+                    escape_html(&person.assigned_person_name)
+                )
+            })
+            .collect();
+        let mut notes = String::new();
+        let event = output.event.as_ref().map(|event| {
+            event.for_giver(
+                assignments
+                    .first()
+                    .and_then(|person| person.group_label())
+                    .as_deref(),
+            )
+        });
+        let details = event.map(|event| event.details()).unwrap_or_default();
+        if !details.is_empty() {
+            notes.push_str("<dl>\n");
+            for (label, value) in details {
+                notes.push_str(&format!(
+                    "<dt>{label}</dt><dd>{}</dd>\n",
+                    escape_html(&value)
+                ));
+            }
+            notes.push_str("</dl>\n");
+        }
+        for person in &assignments {
+            let recipient_notes = person.recipient_notes();
+            if recipient_notes.is_empty() {
+                continue;
+            }
+            notes.push_str(&format!(
+                "<h2>About {}</h2>\n<dl>\n",
+                escape_html(&person.assigned_person_name)
+            ));
+            for (label, note) in recipient_notes {
+                notes.push_str(&format!("<dt>{label}</dt><dd>{}</dd>\n", escape_html(note)));
+            }
+            notes.push_str("</dl>\n");
+        }
+
+        let page = format!(
+            "<!doctype html>\n<html lang=\"en\">\n<head><meta charset=\"utf-8\">\
+             <title>Your gift circle assignment</title></head>\n<body>\n\
+             <h1>Hi {}</h1>\n<p>You are giving a gift to {}.</p>\n{notes}\
+             <p>Keep this page to yourself.</p>\n</body>\n</html>\n",
+            escape_html(giver),
+            recipients.join(" and ")
+        );
+        Response::new(200, "text/html; charset=utf-8", page)
+    }
+}
+
+/// Serve the upload form and the draws API on `address` until the process is stopped.
+pub fn serve(address: &str) -> Result<()> {
+    let server = tiny_http::Server::http(address)
+        .map_err(|err| anyhow!("Failed to listen on {address}: {err}"))?;
+    eprintln!("#INFO: Serving draws on http://{address}/");
+
+    let mut draws = Draws::default();
+    for mut request in server.incoming_requests() {
+        let mut body = Vec::new();
+        let response = match request
+            .as_reader()
+            .take(MAX_BODY_BYTES + 1)
+            .read_to_end(&mut body)
+        {
+            Ok(_) if body.len() as u64 > MAX_BODY_BYTES => {
+                Response::error(413, "request body is too large")
+            }
+            Ok(_) => draws.handle(request.method().as_str(), request.url(), &body),
+            Err(err) => Response::error(400, &err.to_string()),
+        };
+
+        let mut reply =
+            tiny_http::Response::from_data(response.body).with_status_code(response.status);
+        let mut headers = vec![("Content-Type", response.content_type.to_string())];
+        if let Some(file_name) = response.file_name {
+            headers.push((
+                "Content-Disposition",
+                format!("attachment; filename=\"{file_name}\""),
+            ));
+        }
+        for (name, value) in headers {
+            if let Ok(header) = tiny_http::Header::from_bytes(name, value) {
+                reply.add_header(header);
+            }
+        }
+        if let Err(err) = request.respond(reply) {
+            eprintln!("#WARN: Failed to send response: {err}");
+        }
+    }
+    Ok(())
+}
+
+const INDEX_HTML: &str = r#"<!doctype html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<title>Gift circle</title>
+<style>
+  body { font-family: sans-serif; max-width: 40em; margin: 2em auto; padding: 0 1em; }
+  label { display: block; margin: 1em 0 0.3em; }
+  #error { color: #a00; }
+</style>
+</head>
+<body>
+<h1>Gift circle</h1>
+<form id="draw">
+  <label for="participants">Participants CSV file</label>
+  <input id="participants" type="file" accept=".csv,text/csv" required>
+  <label for="mode">Who may give to whom</label>
+  <select id="mode">
+    <option value="plain">Anyone, in one big circle</option>
+    <option value="grouped">Never someone in your own group, in one big circle</option>
+    <option value="derangement">Anyone, in several smaller loops</option>
+    <option value="derangement-groups">Never someone in your own group, in several smaller loops</option>
+  </select>
+  <label for="min-cycle-length">Smallest loop (3 or more stops two people swapping gifts)</label>
+  <input id="min-cycle-length" type="number" min="2" value="2">
+  <label for="gifts">Gifts per person</label>
+  <input id="gifts" type="number" min="1" value="1">
+  <p><button type="submit">Draw</button></p>
+</form>
+<p id="error"></p>
+<div id="result" hidden>
+  <h2>Private links</h2>
+  <p>Send each person only their own link; it shows nobody else's assignment.</p>
+  <ul id="links"></ul>
+  <p><a id="download">Download all assignments as CSV</a> (this reveals everyone's recipient)</p>
+</div>
+<script>
+document.getElementById("draw").addEventListener("submit", async (event) => {
+  event.preventDefault();
+  const error = document.getElementById("error");
+  error.textContent = "";
+  const file = document.getElementById("participants").files[0];
+  const choice = document.getElementById("mode").value;
+  const minCycleLength = Number(document.getElementById("min-cycle-length").value);
+  const mode = choice.startsWith("derangement")
+    ? { type: "derangement", use_groups: choice === "derangement-groups", min_cycle_length: minCycleLength }
+    : { type: choice };
+  const response = await fetch("/draws", {
+    method: "POST",
+    headers: { "Content-Type": "application/json" },
+    body: JSON.stringify({
+      participants_csv: await file.text(),
+      mode,
+      gifts_per_person: Number(document.getElementById("gifts").value),
+    }),
+  });
+  const body = await response.json();
+  if (!response.ok) {
+    error.textContent = body.error;
+    return;
+  }
+  const links = document.getElementById("links");
+  links.replaceChildren(...body.links.map((link) => {
+    const item = document.createElement("li");
+    const url = new URL(link.url, window.location.href).href;
+    item.textContent = link.name + ": ";
+    const anchor = document.createElement("a");
+    anchor.href = url;
+    anchor.textContent = url;
+    item.append(anchor);
+    return item;
+  }));
+  document.getElementById("download").href = body.url + "?format=csv";
+  document.getElementById("result").hidden = false;
+});
+</script>
+</body>
+</html>
+"#;
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::expect_used)]
+mod tests {
+    use super::*;
+
+    const CSV: &str = "name,email_address,group_number\n\
+                       Jack,,1\nJill,,1\nBob,,2\nAlice,,2\nCarol,,3\nDave,,3\n";
+
+    fn create(draws: &mut Draws, request: &str) -> DrawCreated {
+        let response = draws.handle("POST", "/draws", request.as_bytes());
+        assert_eq!(
+            response.status,
+            201,
+            "{}",
+            String::from_utf8_lossy(&response.body)
+        );
+        serde_json::from_slice(&response.body).unwrap()
+    }
+
+    #[test]
+    fn draws_from_uploaded_csv_and_serves_results() {
+        let mut draws = Draws::default();
+        let request = serde_json::json!({
+            "participants_csv": CSV,
+            "mode": { "type": "grouped" },
+            "seed": 7,
+        });
+        let created = create(&mut draws, &request.to_string());
+        assert_eq!(created.links.len(), 6);
+
+        let response = draws.handle("GET", &created.url, b"");
+        assert_eq!(response.status, 200);
+        let output: GiftCircleOutput = serde_json::from_slice(&response.body).unwrap();
+        assert!(output.used_groups);
+        assert_eq!(output.metadata.unwrap().seed, 7);
+
+        let response = draws.handle("GET", &format!("{}?format=csv", created.url), b"");
+        assert_eq!(response.content_type, "text/csv; charset=utf-8");
+        assert!(response.body.starts_with(b"name,email_address"));
+    }
+
+    #[test]
+    fn private_link_shows_only_the_givers_recipient() {
+        let mut draws = Draws::default();
+        let request = serde_json::json!({ "participants_csv": CSV, "seed": 3 });
+        let created = create(&mut draws, &request.to_string());
+        let output: GiftCircleOutput =
+            serde_json::from_slice(&draws.handle("GET", &created.url, b"").body).unwrap();
+
+        for link in &created.links {
+            let page = String::from_utf8(draws.handle("GET", &link.url, b"").body).unwrap();
+            assert!(page.contains(&format!("Hi {}", link.name)));
+            for person in &output.people {
+                let shown =
+                    page.contains(&format!("<strong>{}</strong>", person.assigned_person_name));
+                assert_eq!(shown, person.name == link.name);
+            }
+        }
+    }
+
+    #[test]
+    fn private_link_shows_the_event() {
+        let mut draws = Draws::default();
+        let request = serde_json::json!({
+            "participants_csv": CSV,
+            "event": { "budget": "20", "currency": "EUR", "group_budgets": { "1": "10" } },
+        });
+        let created = create(&mut draws, &request.to_string());
+        for link in &created.links {
+            let page = String::from_utf8(draws.handle("GET", &link.url, b"").body).unwrap();
+            let budget = if ["Jack", "Jill"].contains(&link.name.as_str()) {
+                "10 EUR"
+            } else {
+                "20 EUR"
+            };
+            assert!(page.contains(&format!("<dt>Spending limit</dt><dd>{budget}</dd>")));
+        }
+    }
+
+    #[test]
+    fn reports_bad_requests_and_unknown_paths() {
+        let mut draws = Draws::default();
+        let too_few = serde_json::json!({ "participants_csv": "name\nJack\nJill\n" });
+        let response = draws.handle("POST", "/draws", too_few.to_string().as_bytes());
+        assert_eq!(response.status, 400);
+
+        assert_eq!(draws.handle("POST", "/draws", b"not json").status, 400);
+        assert_eq!(draws.handle("GET", "/draws/unknown", b"").status, 404);
+        assert_eq!(draws.handle("GET", "/reveal/unknown", b"").status, 404);
+        assert_eq!(draws.handle("DELETE", "/draws", b"").status, 405);
+        assert_eq!(draws.handle("GET", "/", b"").status, 200);
+    }
+
+    #[test]
+    fn escapes_names_in_pages() {
+        assert_eq!(
+            escape_html("<b>Tom & \"Jo\"</b>"),
+            "&lt;b&gt;Tom &amp; &quot;Jo&quot;&lt;/b&gt;"
+        );
+    }
+}
@@ -0,0 +1,229 @@
+//! Repeated draws from the same input, to see how evenly the generator spreads pairings.
+
+use std::collections::BTreeMap;
+
+use rand::rngs::StdRng;
+use rand::{RngExt, SeedableRng};
+use serde::Serialize;
+
+use crate::error::Result;
+use crate::gift_circle::{generate_with_options, validate_people, GenerateOptions, Rules};
+use crate::mode::{GiftMode, Sampling};
+use crate::people::People;
+
+/// How many draws needed a given number of attempts.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct AttemptCount {
+    pub attempts: u16,
+    pub draws: usize,
+}
+
+/// How often givers in one group gave to recipients in another, against how often they
+/// would if every giver picked evenly among the recipients the rules allow them.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct GroupBias {
+    /// Group of the givers; empty for people without a group.
+    pub giver_group: String,
+    /// Group of the recipients; empty for people without a group.
+    pub recipient_group: String,
+    /// Gifts from the giver group to the recipient group over every draw.
+    pub gifts: usize,
+    /// Share of the giver group's gifts that went to the recipient group.
+    pub share: f64,
+    /// Share of the giver group's allowed pairings that are with the recipient group.
+    pub allowed_share: f64,
+    /// `share` divided by `allowed_share`; above 1 the recipient group is favoured.
+    pub bias: f64,
+}
+
+/// What repeated draws from the same input produced.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct SimulationReport {
+    pub draws: usize,
+    /// Seed that each draw's own seed was derived from.
+    pub seed: u64,
+    pub mode: GiftMode,
+    pub sampling: Sampling,
+    /// Participant names in input order, labelling the rows and columns of `pair_counts`.
+    pub names: Vec<String>,
+    /// How many draws gave each giver (row) each recipient (column).
+    pub pair_counts: Vec<Vec<usize>>,
+    /// How many draws needed each number of attempts, fewest attempts first.
+    pub attempts: Vec<AttemptCount>,
+    /// Bias between every pair of groups that may give to each other.
+    pub groups: Vec<GroupBias>,
+}
+
+/// Make `draws` draws from `people` and tally the results.
+///
+/// Each draw uses its own seed, the next number from a generator seeded with `seed`, so
+/// any single draw can be repeated with `generate --seed`.
+///
+/// # Errors
+///
+/// Returns [`GiftCircleError`](crate::GiftCircleError) when validation fails or any draw
+/// finds no circle.
+pub fn simulate(
+    people: &People,
+    options: &GenerateOptions,
+    draws: usize,
+    seed: u64,
+) -> Result<SimulationReport> {
+    let names: Vec<String> = people.iter().map(|person| person.name.clone()).collect();
+    let index_of = |name: &str| names.iter().position(|other| other == name);
+
+    let mut pair_counts = vec![vec![0; names.len()]; names.len()];
+    let mut attempts: BTreeMap<u16, usize> = BTreeMap::new();
+    let mut seeds = StdRng::seed_from_u64(seed);
+    for _ in 0..draws {
+        let mut rng = StdRng::seed_from_u64(seeds.random());
+        let output = generate_with_options(people, options, &mut rng)?;
+        *attempts.entry(output.attempts).or_default() += 1;
+        for person in &output.people {
+            if let (Some(giver), Some(recipient)) = (
+                index_of(&person.name),
+                index_of(&person.assigned_person_name),
+            ) {
+                pair_counts[giver][recipient] += 1;
+            }
+        }
+    }
+
+    let validated = validate_people(people, options.mode)?;
+    let rules = Rules::new(people, &validated)?;
+    let groups = group_bias(people, &rules, &pair_counts);
+    Ok(SimulationReport {
+        draws,
+        seed,
+        mode: options.mode,
+        sampling: options.sampling,
+        names,
+        pair_counts,
+        attempts: attempts
+            .into_iter()
+            .map(|(attempts, draws)| AttemptCount { attempts, draws })
+            .collect(),
+        groups,
+    })
+}
+
+fn group_bias(people: &People, rules: &Rules, pair_counts: &[Vec<usize>]) -> Vec<GroupBias> {
+    let mut groups: Vec<String> = Vec::new();
+    let group_of: Vec<usize> = people
+        .iter()
+        .map(|person| {
+            let label = person.group_label().unwrap_or_default();
+            groups
+                .iter()
+                .position(|group| *group == label)
+                .unwrap_or_else(|| {
+                    groups.push(label);
+                    groups.len() - 1
+                })
+        })
+        .collect();
+
+    // Gifts and allowed pairings from each giver group to each recipient group.
+    let mut totals: BTreeMap<(usize, usize), (usize, usize)> = BTreeMap::new();
+    for (giver, &giver_group) in group_of.iter().enumerate() {
+        for (recipient, &recipient_group) in group_of.iter().enumerate() {
+            let entry = totals.entry((giver_group, recipient_group)).or_default();
+            entry.0 += pair_counts[giver][recipient];
+            entry.1 += usize::from(rules.allows(giver, recipient));
+        }
+    }
+
+    let from_group = |giver_group: usize| {
+        totals
+            .iter()
+            .filter(|((from, _), _)| *from == giver_group)
+            .fold((0, 0), |(gifts, allowed), (_, &(g, a))| {
+                (gifts + g, allowed + a)
+            })
+    };
+    totals
+        .iter()
+        .filter(|(_, &(_, allowed))| allowed > 0)
+        .map(|(&(giver_group, recipient_group), &(gifts, allowed))| {
+            let (group_gifts, group_allowed) = from_group(giver_group);
+            let share = gifts as f64 / group_gifts.max(1) as f64;
+            let allowed_share = allowed as f64 / group_allowed as f64;
+            GroupBias {
+                giver_group: groups[giver_group].clone(),
+                recipient_group: groups[recipient_group].clone(),
+                gifts,
+                share,
+                allowed_share,
+                bias: share / allowed_share,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::expect_used)]
+mod tests {
+    use super::*;
+    use crate::person::Participant;
+
+    fn people() -> People {
+        People::from(vec![
+            Participant::new("Father", 1),
+            Participant::new("Mother", 1),
+            Participant::new("Grandpa", 1),
+            Participant::new("Son", 2),
+            Participant::new("Daughter", 2),
+            Participant::new("Aunt", 3),
+        ])
+    }
+
+    #[test]
+    fn report_tallies_every_draw() {
+        let options = GenerateOptions::from(GiftMode::Grouped);
+        let report = simulate(&people(), &options, 300, 9).unwrap();
+        assert_eq!(report, simulate(&people(), &options, 300, 9).unwrap());
+
+        assert_eq!(report.names.len(), 6);
+        for (index, row) in report.pair_counts.iter().enumerate() {
+            assert_eq!(row.iter().sum::<usize>(), 300);
+            assert_eq!(row[index], 0);
+        }
+        assert_eq!(report.pair_counts[0][1], 0, "Father never gives to Mother");
+        assert_eq!(
+            report
+                .attempts
+                .iter()
+                .map(|count| count.draws)
+                .sum::<usize>(),
+            300
+        );
+
+        assert!(report
+            .groups
+            .iter()
+            .all(|bias| bias.giver_group != bias.recipient_group));
+        let from_first: f64 = report
+            .groups
+            .iter()
+            .filter(|bias| bias.giver_group == "1")
+            .map(|bias| bias.share)
+            .sum();
+        assert!((from_first - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn each_draw_can_be_repeated_from_its_seed() {
+        let options = GenerateOptions::from(GiftMode::Grouped);
+        let report = simulate(&people(), &options, 1, 4).unwrap();
+        let seed = StdRng::seed_from_u64(4).random();
+        let output = crate::generate_with_seed(&people(), &options, seed).unwrap();
+        for person in output.people {
+            let giver = report.names.iter().position(|name| *name == person.name);
+            let recipient = report
+                .names
+                .iter()
+                .position(|name| *name == person.assigned_person_name);
+            assert_eq!(report.pair_counts[giver.unwrap()][recipient.unwrap()], 1);
+        }
+    }
+}
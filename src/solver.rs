@@ -0,0 +1,447 @@
+//! Randomised backtracking search used when the fast heuristics fail to find a circle.
+
+use std::collections::HashMap;
+
+use rand::prelude::{Rng, RngExt, SliceRandom};
+
+/// Counts of not-yet-placed participants per group, used to prune branches that can
+/// no longer avoid putting two members of the same group next to each other.
+struct GroupCounts<'a> {
+    groups: &'a [u16],
+    remaining: HashMap<u16, usize>,
+}
+
+impl<'a> GroupCounts<'a> {
+    fn new(groups: &'a [u16]) -> Self {
+        let mut remaining = HashMap::new();
+        for &group in groups {
+            *remaining.entry(group).or_insert(0) += 1;
+        }
+        Self { groups, remaining }
+    }
+
+    fn visit(&mut self, index: usize) {
+        if let Some(count) = self.remaining.get_mut(&self.groups[index]) {
+            *count -= 1;
+        }
+    }
+
+    fn unvisit(&mut self, index: usize) {
+        *self.remaining.entry(self.groups[index]).or_insert(0) += 1;
+    }
+
+    /// Whether the unplaced participants can still be laid out between `current` and
+    /// `start` without two members of one group sitting next to each other.
+    fn can_complete(&self, current: usize, start: usize, unplaced: usize) -> bool {
+        let current_group = self.groups[current];
+        let start_group = self.groups[start];
+        self.remaining.iter().all(|(&group, &count)| {
+            let blocked = usize::from(group == current_group) + usize::from(group == start_group);
+            match unplaced.checked_sub(blocked) {
+                Some(slots) => count <= slots.div_ceil(2),
+                None => count == 0,
+            }
+        })
+    }
+}
+
+/// Find a circle through every index in `0..len` where each giver/recipient pair,
+/// including the wrap-around from last to first, satisfies `allowed`.
+///
+/// Candidates are explored in random order so repeated calls yield different circles.
+/// When `groups` is given it must agree with `allowed` (members of one group never
+/// give to each other) and is used to prune the search. Returns `None` only when the
+/// search has proved that no such circle exists.
+pub(crate) fn find_circle(
+    len: usize,
+    groups: Option<&[u16]>,
+    allowed: impl Fn(usize, usize) -> bool,
+    rng: &mut impl Rng,
+) -> Option<Vec<usize>> {
+    search_circle(len, groups, allowed, usize::MAX, rng)
+}
+
+/// Like [`find_circle`] without groups, but give up after placing someone `max_steps`
+/// times, so `None` may also mean the search was cut short.
+pub(crate) fn find_circle_within(
+    len: usize,
+    allowed: impl Fn(usize, usize) -> bool,
+    max_steps: usize,
+    rng: &mut impl Rng,
+) -> Option<Vec<usize>> {
+    search_circle(len, None, allowed, max_steps, rng)
+}
+
+fn search_circle(
+    len: usize,
+    groups: Option<&[u16]>,
+    allowed: impl Fn(usize, usize) -> bool,
+    max_steps: usize,
+    rng: &mut impl Rng,
+) -> Option<Vec<usize>> {
+    if len == 0 {
+        return Some(Vec::new());
+    }
+    // A circle gives everyone a different recipient, so without such a pairing the
+    // search below could only fail, slowly.
+    if len > 1 && perfect_matching(&allowed_recipients(len, &allowed)).is_none() {
+        return None;
+    }
+
+    let start = rng.random_range(0..len);
+    let mut visited = vec![false; len];
+    let mut group_counts = groups.map(GroupCounts::new);
+    let mut path = Vec::with_capacity(len);
+
+    let candidates = |current: usize, visited: &[bool], rng: &mut _| {
+        let mut next: Vec<usize> = (0..len)
+            .filter(|&index| !visited[index] && allowed(current, index))
+            .collect();
+        next.shuffle(rng);
+        next
+    };
+
+    visited[start] = true;
+    if let Some(counts) = group_counts.as_mut() {
+        counts.visit(start);
+    }
+    path.push(start);
+    if len == 1 {
+        return allowed(start, start).then_some(path);
+    }
+
+    let mut frames = vec![candidates(start, &visited, rng)];
+    let mut steps = 0;
+    while let Some(frame) = frames.last_mut() {
+        let Some(next) = frame.pop() else {
+            frames.pop();
+            if let Some(index) = path.pop() {
+                visited[index] = false;
+                if let Some(counts) = group_counts.as_mut() {
+                    counts.unvisit(index);
+                }
+            }
+            continue;
+        };
+        steps += 1;
+        if steps > max_steps {
+            return None;
+        }
+
+        visited[next] = true;
+        if let Some(counts) = group_counts.as_mut() {
+            counts.visit(next);
+        }
+        path.push(next);
+
+        let unplaced = len - path.len();
+        let promising = if unplaced == 0 {
+            allowed(next, start)
+        } else {
+            group_counts
+                .as_ref()
+                .is_none_or(|counts| counts.can_complete(next, start, unplaced))
+                && every_unplaced_is_reachable(&visited, next, start, &allowed)
+        };
+
+        if unplaced == 0 && promising {
+            return Some(path);
+        }
+        if promising {
+            frames.push(candidates(next, &visited, rng));
+        } else {
+            path.pop();
+            visited[next] = false;
+            if let Some(counts) = group_counts.as_mut() {
+                counts.unvisit(next);
+            }
+        }
+    }
+
+    None
+}
+
+/// Links chosen so far while building a permutation, as chains of giver→recipient.
+struct Chains {
+    next: Vec<Option<usize>>,
+    prev: Vec<Option<usize>>,
+}
+
+impl Chains {
+    fn new(len: usize) -> Self {
+        Self {
+            next: vec![None; len],
+            prev: vec![None; len],
+        }
+    }
+
+    /// The first participant of the chain ending at `tail`, and how many people it holds.
+    fn head_and_length(&self, tail: usize) -> (usize, usize) {
+        let mut head = tail;
+        let mut length = 1;
+        while let Some(previous) = self.prev[head] {
+            head = previous;
+            length += 1;
+        }
+        (head, length)
+    }
+
+    fn link(&mut self, giver: usize, recipient: usize) {
+        self.next[giver] = Some(recipient);
+        self.prev[recipient] = Some(giver);
+    }
+
+    fn unlink(&mut self, giver: usize) {
+        if let Some(recipient) = self.next[giver].take() {
+            self.prev[recipient] = None;
+        }
+    }
+
+    /// Recipients `giver` could still be linked to without closing a loop shorter than
+    /// `min_cycle_length`.
+    fn open_recipients<'a>(
+        &'a self,
+        giver: usize,
+        min_cycle_length: usize,
+        allowed: &'a impl Fn(usize, usize) -> bool,
+    ) -> impl Iterator<Item = usize> + 'a {
+        let (head, length) = self.head_and_length(giver);
+        (0..self.prev.len()).filter(move |&recipient| {
+            self.prev[recipient].is_none()
+                && allowed(giver, recipient)
+                && (recipient != head || length >= min_cycle_length)
+        })
+    }
+}
+
+/// Find a permutation of `0..len`, returned as each giver's recipient, where every
+/// giver/recipient pair satisfies `allowed` and every loop has at least
+/// `min_cycle_length` people.
+///
+/// Givers and candidates are explored in random order so repeated calls yield different
+/// assignments. Returns `None` only when the search has proved none exists.
+pub(crate) fn find_derangement(
+    len: usize,
+    min_cycle_length: usize,
+    allowed: impl Fn(usize, usize) -> bool,
+    rng: &mut impl Rng,
+) -> Option<Vec<usize>> {
+    // Any pairing of givers with different recipients splits into loops of at least two
+    // people, so for those loops a pairing is the whole answer, and otherwise the search
+    // below needs one to exist.
+    let mut recipients = allowed_recipients(len, &allowed);
+    if min_cycle_length <= 2 {
+        recipients
+            .iter_mut()
+            .for_each(|options| options.shuffle(rng));
+        return perfect_matching(&recipients);
+    }
+    perfect_matching(&recipients)?;
+
+    let mut order: Vec<usize> = (0..len).collect();
+    order.shuffle(rng);
+    let mut chains = Chains::new(len);
+
+    let candidates = |giver: usize, chains: &Chains, rng: &mut _| {
+        let mut next: Vec<usize> = chains
+            .open_recipients(giver, min_cycle_length, &allowed)
+            .collect();
+        next.shuffle(rng);
+        next
+    };
+
+    let Some(&first) = order.first() else {
+        return Some(Vec::new());
+    };
+    let mut frames = vec![candidates(first, &chains, rng)];
+    while !frames.is_empty() {
+        let depth = frames.len() - 1;
+        let giver = order[depth];
+        chains.unlink(giver);
+        let Some(recipient) = frames[depth].pop() else {
+            frames.pop();
+            continue;
+        };
+
+        chains.link(giver, recipient);
+        let Some(&next_giver) = order.get(frames.len()) else {
+            return chains.next.iter().copied().collect();
+        };
+
+        let promising = order[frames.len()..].iter().all(|&waiting| {
+            chains
+                .open_recipients(waiting, min_cycle_length, &allowed)
+                .next()
+                .is_some()
+        });
+        if promising {
+            frames.push(candidates(next_giver, &chains, rng));
+        }
+    }
+
+    None
+}
+
+/// Each giver's allowed recipients, never including the giver.
+fn allowed_recipients(len: usize, allowed: &impl Fn(usize, usize) -> bool) -> Vec<Vec<usize>> {
+    (0..len)
+        .map(|giver| {
+            (0..len)
+                .filter(|&recipient| recipient != giver && allowed(giver, recipient))
+                .collect()
+        })
+        .collect()
+}
+
+/// Pair every giver with a different recipient from their `options`, trying options in
+/// the order given, returned as each giver's recipient. Returns `None` when no such
+/// pairing exists, for example when some set of givers shares fewer recipients than
+/// there are givers in it.
+fn perfect_matching(options: &[Vec<usize>]) -> Option<Vec<usize>> {
+    /// Find `giver` a recipient, moving earlier givers to other recipients if needed.
+    fn assign(
+        giver: usize,
+        options: &[Vec<usize>],
+        giver_of: &mut [Option<usize>],
+        tried: &mut [bool],
+    ) -> bool {
+        for &recipient in &options[giver] {
+            if tried[recipient] {
+                continue;
+            }
+            tried[recipient] = true;
+            let free = match giver_of[recipient] {
+                None => true,
+                Some(other) => assign(other, options, giver_of, tried),
+            };
+            if free {
+                giver_of[recipient] = Some(giver);
+                return true;
+            }
+        }
+        false
+    }
+
+    let len = options.len();
+    let mut giver_of = vec![None; len];
+    for giver in 0..len {
+        if !assign(giver, options, &mut giver_of, &mut vec![false; len]) {
+            return None;
+        }
+    }
+    let mut recipients = vec![0; len];
+    for (recipient, giver) in giver_of.into_iter().enumerate() {
+        recipients[giver?] = recipient;
+    }
+    Some(recipients)
+}
+
+/// Every unplaced participant still needs someone to give to them and someone to give to.
+fn every_unplaced_is_reachable(
+    visited: &[bool],
+    current: usize,
+    start: usize,
+    allowed: &impl Fn(usize, usize) -> bool,
+) -> bool {
+    let open = |index: usize| !visited[index];
+    (0..visited.len())
+        .filter(|&index| open(index))
+        .all(|index| {
+            let has_giver = (0..visited.len()).any(|from| {
+                from != index && (open(from) || from == current) && allowed(from, index)
+            });
+            let has_recipient = (0..visited.len())
+                .any(|to| to != index && (open(to) || to == start) && allowed(index, to));
+            has_giver && has_recipient
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+
+    fn assert_circle(path: &[usize], len: usize, allowed: impl Fn(usize, usize) -> bool) {
+        let mut sorted = path.to_vec();
+        sorted.sort_unstable();
+        assert_eq!(sorted, (0..len).collect::<Vec<_>>());
+        for (position, &giver) in path.iter().enumerate() {
+            assert!(allowed(giver, path[(position + 1) % path.len()]));
+        }
+    }
+
+    #[test]
+    fn finds_circle_for_tight_group_layout() {
+        let groups = [1, 1, 1, 1, 2, 3, 2, 3];
+        let allowed = |a: usize, b: usize| groups[a] != groups[b];
+        let mut rng = StdRng::seed_from_u64(3);
+        let path = find_circle(groups.len(), Some(&groups), allowed, &mut rng).unwrap();
+        assert_circle(&path, groups.len(), allowed);
+    }
+
+    #[test]
+    fn proves_impossible_group_layout() {
+        let groups = [1, 1, 1, 2, 3];
+        let allowed = |a: usize, b: usize| groups[a] != groups[b];
+        let mut rng = StdRng::seed_from_u64(3);
+        assert!(find_circle(groups.len(), Some(&groups), allowed, &mut rng).is_none());
+    }
+
+    #[test]
+    fn finds_derangement_without_short_loops() {
+        let groups = [1, 1, 2, 2, 3, 3, 4];
+        let allowed = |a: usize, b: usize| groups[a] != groups[b];
+        let mut rng = StdRng::seed_from_u64(8);
+        let recipients = find_derangement(groups.len(), 3, allowed, &mut rng).unwrap();
+        let mut sorted = recipients.clone();
+        sorted.sort_unstable();
+        assert_eq!(sorted, (0..groups.len()).collect::<Vec<_>>());
+        for (giver, &recipient) in recipients.iter().enumerate() {
+            assert!(allowed(giver, recipient));
+            assert_ne!(recipients[recipient], giver);
+        }
+    }
+
+    #[test]
+    fn proves_impossible_derangement() {
+        let allowed = |a: usize, b: usize| a != b;
+        let mut rng = StdRng::seed_from_u64(8);
+        assert!(find_derangement(4, 5, allowed, &mut rng).is_none());
+    }
+
+    #[test]
+    fn honours_arbitrary_rules_without_groups() {
+        let allowed = |a: usize, b: usize| a != b && (a + 1) % 5 != b;
+        let mut rng = StdRng::seed_from_u64(11);
+        let path = find_circle(5, None, allowed, &mut rng).unwrap();
+        assert_circle(&path, 5, allowed);
+    }
+
+    /// Fourteen givers who may only give to the first thirteen people, in a crowd of forty.
+    fn crowded_recipients(a: usize, b: usize) -> bool {
+        a != b && (a >= 14 || b < 13)
+    }
+
+    #[test]
+    fn proves_too_few_recipients_without_searching() {
+        let mut rng = StdRng::seed_from_u64(2);
+        assert!(find_circle(40, None, crowded_recipients, &mut rng).is_none());
+        assert!(find_derangement(40, 2, crowded_recipients, &mut rng).is_none());
+        assert!(find_derangement(40, 3, crowded_recipients, &mut rng).is_none());
+    }
+
+    #[test]
+    fn pairs_everyone_for_loops_of_two_or_more() {
+        let allowed = |a: usize, b: usize| a != b && (a + 1) % 6 != b;
+        let mut rng = StdRng::seed_from_u64(4);
+        let recipients = find_derangement(6, 2, allowed, &mut rng).unwrap();
+        let mut sorted = recipients.clone();
+        sorted.sort_unstable();
+        assert_eq!(sorted, (0..6).collect::<Vec<_>>());
+        for (giver, &recipient) in recipients.iter().enumerate() {
+            assert!(allowed(giver, recipient));
+        }
+    }
+}
@@ -0,0 +1,270 @@
+//! Exact counting of circles and loops, used to pick assignments uniformly at random and
+//! to report how many assignments the rules allow.
+
+use rand::prelude::{Rng, RngExt};
+
+/// Most participants [`CircleCounts`] and [`LoopCounts`] handle; their tables grow as
+/// `2^len`.
+pub(crate) const MAX_EXACT_LEN: usize = 16;
+
+/// How many ways each partial circle can be continued, built once for a set of rules.
+///
+/// Every circle is counted as the one path that starts at person 0 and returns to them,
+/// so picking a path with probability proportional to these counts picks every circle
+/// with equal probability.
+pub(crate) struct CircleCounts {
+    len: usize,
+    allowed: Vec<bool>,
+    /// Paths from person 0 through exactly the people in a set, by set and last person.
+    /// Bit `i` of the set, and last person `i`, stand for person `i + 1`.
+    paths: Vec<u64>,
+}
+
+impl CircleCounts {
+    /// Count the circles through every index in `0..len` where each giver/recipient pair
+    /// satisfies `allowed`. Returns `None` when there are too many people to count.
+    pub(crate) fn new(len: usize, allowed: impl Fn(usize, usize) -> bool) -> Option<Self> {
+        if !(2..=MAX_EXACT_LEN).contains(&len) {
+            return None;
+        }
+        let allowed: Vec<bool> = (0..len * len)
+            .map(|pair| allowed(pair / len, pair % len))
+            .collect();
+        let others = len - 1;
+        let mut paths = vec![0u64; (1 << others) * others];
+        for last in 0..others {
+            if allowed[last + 1] {
+                paths[(1 << last) * others + last] = 1;
+            }
+        }
+        for set in 1..(1usize << others) {
+            for last in 0..others {
+                let count = paths[set * others + last];
+                if count == 0 {
+                    continue;
+                }
+                for next in 0..others {
+                    if set & (1 << next) == 0 && allowed[(last + 1) * len + next + 1] {
+                        paths[(set | 1 << next) * others + next] += count;
+                    }
+                }
+            }
+        }
+        Some(Self {
+            len,
+            allowed,
+            paths,
+        })
+    }
+
+    fn allows(&self, giver: usize, recipient: usize) -> bool {
+        self.allowed[giver * self.len + recipient]
+    }
+
+    /// Paths through `set` ending at `last` that can step on to `next`.
+    fn weights(&self, set: usize, next: usize) -> Vec<u64> {
+        let others = self.len - 1;
+        (0..others)
+            .map(|last| {
+                if set & (1 << last) != 0 && self.allows(last + 1, next) {
+                    self.paths[set * others + last]
+                } else {
+                    0
+                }
+            })
+            .collect()
+    }
+
+    /// Number of different circles.
+    pub(crate) fn total(&self) -> u64 {
+        self.weights((1 << (self.len - 1)) - 1, 0).iter().sum()
+    }
+
+    /// A circle picked uniformly at random, starting with person 0, or `None` when there
+    /// are no circles.
+    pub(crate) fn sample(&self, rng: &mut impl Rng) -> Option<Vec<usize>> {
+        let mut set = (1usize << (self.len - 1)) - 1;
+        let mut next = 0;
+        let mut reversed = Vec::with_capacity(self.len);
+        while set != 0 {
+            let last = pick(&self.weights(set, next), rng)?;
+            reversed.push(last + 1);
+            set &= !(1 << last);
+            next = last + 1;
+        }
+        reversed.push(0);
+        reversed.reverse();
+        Some(reversed)
+    }
+}
+
+/// How many ways each set of people can be split into loops, built once for a set of
+/// rules, so loops can be picked with every valid split equally likely.
+pub(crate) struct LoopCounts {
+    len: usize,
+    min_cycle_length: usize,
+    allowed: Vec<bool>,
+    /// Paths through exactly a set of people that start at its lowest member, by set and
+    /// last person.
+    paths: Vec<u64>,
+    /// Loops through exactly each set.
+    loops_through: Vec<u64>,
+    /// Ways to split each set into loops of at least `min_cycle_length` people.
+    covers: Vec<u64>,
+}
+
+impl LoopCounts {
+    /// Count the ways to split every index in `0..len` into loops of at least
+    /// `min_cycle_length` people where each giver/recipient pair satisfies `allowed`.
+    /// Returns `None` when there are too many people to count.
+    pub(crate) fn new(
+        len: usize,
+        min_cycle_length: usize,
+        allowed: impl Fn(usize, usize) -> bool,
+    ) -> Option<Self> {
+        if len > MAX_EXACT_LEN {
+            return None;
+        }
+        let allowed: Vec<bool> = (0..len * len)
+            .map(|pair| allowed(pair / len, pair % len))
+            .collect();
+        let sets = 1usize << len;
+
+        let mut paths = vec![0u64; sets * len];
+        let mut loops_through = vec![0u64; sets];
+        for person in 0..len {
+            paths[(1 << person) * len + person] = 1;
+        }
+        for set in 1..sets {
+            let start = set.trailing_zeros() as usize;
+            for last in 0..len {
+                let count = paths[set * len + last];
+                if count == 0 {
+                    continue;
+                }
+                if last != start && allowed[last * len + start] {
+                    loops_through[set] += count;
+                }
+                for next in start + 1..len {
+                    if set & (1 << next) == 0 && allowed[last * len + next] {
+                        paths[(set | 1 << next) * len + next] += count;
+                    }
+                }
+            }
+        }
+
+        let mut counts = Self {
+            len,
+            min_cycle_length,
+            allowed,
+            paths,
+            loops_through,
+            covers: vec![0u64; sets],
+        };
+        // Every way to cover a set is one loop through its lowest member and a way to
+        // cover the rest.
+        counts.covers[0] = 1;
+        for set in 1..sets {
+            counts.covers[set] = counts.first_loops(set).iter().map(|(_, ways)| ways).sum();
+        }
+        Some(counts)
+    }
+
+    fn allows(&self, giver: usize, recipient: usize) -> bool {
+        self.allowed[giver * self.len + recipient]
+    }
+
+    /// Each loop `set` could start with, one through its lowest member, and how many
+    /// ways the rest of `set` can then be covered.
+    fn first_loops(&self, set: usize) -> Vec<(usize, u64)> {
+        let lowest = set & set.wrapping_neg();
+        let rest = set ^ lowest;
+        let mut loops = Vec::new();
+        let mut others = rest;
+        loop {
+            let looped = others | lowest;
+            if looped.count_ones() as usize >= self.min_cycle_length {
+                let ways = self.loops_through[looped] * self.covers[set ^ looped];
+                if ways > 0 {
+                    loops.push((looped, ways));
+                }
+            }
+            if others == 0 {
+                break;
+            }
+            others = (others - 1) & rest;
+        }
+        loops
+    }
+
+    /// Number of different ways to split everyone into loops.
+    pub(crate) fn total(&self) -> u64 {
+        self.covers[(1 << self.len) - 1]
+    }
+
+    /// Loops picked uniformly at random among every valid split, each listed in gift
+    /// order from its lowest index, or `None` when there are none.
+    pub(crate) fn sample(&self, rng: &mut impl Rng) -> Option<Vec<Vec<usize>>> {
+        let mut set = (1usize << self.len) - 1;
+        let mut loops = Vec::new();
+        while set != 0 {
+            let choices = self.first_loops(set);
+            let weights: Vec<u64> = choices.iter().map(|&(_, ways)| ways).collect();
+            let looped = choices[pick(&weights, rng)?].0;
+            loops.push(self.sample_loop(looped, rng)?);
+            set ^= looped;
+        }
+        Some(loops)
+    }
+
+    /// A loop through exactly `set` picked uniformly, starting at its lowest member.
+    fn sample_loop(&self, set: usize, rng: &mut impl Rng) -> Option<Vec<usize>> {
+        let len = self.len;
+        let start = set.trailing_zeros() as usize;
+        let weights: Vec<u64> = (0..len)
+            .map(|last| {
+                if last != start && self.allows(last, start) {
+                    self.paths[set * len + last]
+                } else {
+                    0
+                }
+            })
+            .collect();
+        let mut last = pick(&weights, rng)?;
+        let mut set = set;
+        let mut reversed = vec![last];
+        while set != 1 << start {
+            set &= !(1 << last);
+            let weights: Vec<u64> = (0..len)
+                .map(|previous| {
+                    if self.allows(previous, last) {
+                        self.paths[set * len + previous]
+                    } else {
+                        0
+                    }
+                })
+                .collect();
+            last = pick(&weights, rng)?;
+            reversed.push(last);
+        }
+        reversed.reverse();
+        Some(reversed)
+    }
+}
+
+/// An index picked with probability proportional to its weight.
+fn pick(weights: &[u64], rng: &mut impl Rng) -> Option<usize> {
+    let total: u64 = weights.iter().sum();
+    if total == 0 {
+        return None;
+    }
+    let mut target = rng.random_range(0..total);
+    weights.iter().position(|&weight| {
+        if target < weight {
+            true
+        } else {
+            target -= weight;
+            false
+        }
+    })
+}
@@ -0,0 +1,450 @@
+//! Checks that an existing set of assignments still follows the rules, for example after
+//! it has been edited by hand.
+
+use std::collections::{HashMap, HashSet};
+
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::gift_circle::cycles_from_recipients;
+use crate::mode::GiftMode;
+use crate::people::People;
+use crate::person::{Participant, Person};
+
+/// One way an assignments file breaks the rules. Rows count assignment rows from 1.
+#[derive(Clone, Debug, Error, PartialEq, Eq, Serialize)]
+#[serde(tag = "problem", rename_all = "snake_case")]
+pub enum Violation {
+    #[error("row {row}: giver {name:?} is not a participant")]
+    UnknownGiver { name: String, row: usize },
+    #[error("row {row}: recipient {name:?} is not a participant")]
+    UnknownRecipient { name: String, row: usize },
+    #[error("{name:?} has no group")]
+    MissingGroup { name: String },
+    #[error("exclusion refers to unknown participant {name:?}")]
+    UnknownExclusionName { name: String },
+    #[error("row {row}: {name:?} gives to themselves")]
+    SelfAssignment { name: String, row: usize },
+    #[error("row {row}: {giver:?} gives to {recipient:?} from their own group {group:?}")]
+    SameGroup {
+        giver: String,
+        recipient: String,
+        group: String,
+        row: usize,
+    },
+    #[error("row {row}: {giver:?} must never give to {recipient:?}")]
+    Excluded {
+        giver: String,
+        recipient: String,
+        row: usize,
+    },
+    #[error("rows {}: {giver:?} gives to {recipient:?} more than once", rows_list(.rows))]
+    RepeatedPair {
+        giver: String,
+        recipient: String,
+        rows: Vec<usize>,
+    },
+    #[error("{name:?} gives {count} gifts instead of {expected}{}", in_rows(.rows))]
+    WrongGiftCount {
+        name: String,
+        count: usize,
+        expected: usize,
+        rows: Vec<usize>,
+    },
+    #[error("{name:?} receives {count} gifts instead of {expected}{}", in_rows(.rows))]
+    WrongReceiveCount {
+        name: String,
+        count: usize,
+        expected: usize,
+        rows: Vec<usize>,
+    },
+    #[error("assignments form {} separate loops instead of one circle: {}", .loops.len(), loops_list(.loops))]
+    NotOneCircle { loops: Vec<Vec<String>> },
+    #[error("loop of {} is shorter than {min_cycle_length} people", .names.join(" -> "))]
+    ShortLoop {
+        names: Vec<String>,
+        min_cycle_length: usize,
+    },
+}
+
+fn rows_list(rows: &[usize]) -> String {
+    let rows: Vec<String> = rows.iter().map(usize::to_string).collect();
+    rows.join(", ")
+}
+
+fn in_rows(rows: &[usize]) -> String {
+    if rows.is_empty() {
+        String::new()
+    } else {
+        format!(" (rows {})", rows_list(rows))
+    }
+}
+
+fn loops_list(loops: &[Vec<String>]) -> String {
+    let loops: Vec<String> = loops.iter().map(|names| names.join(" -> ")).collect();
+    loops.join("; ")
+}
+
+/// Whether two group labels name the same group; numeric labels match by number, as
+/// they do when drawing.
+fn same_group(left: &str, right: &str) -> bool {
+    match (left.parse::<u16>(), right.parse::<u16>()) {
+        (Ok(left), Ok(right)) => left == right,
+        _ => left == right,
+    }
+}
+
+/// Check `assignments` against the participants, groups and exclusions in `people`.
+///
+/// Every participant must give and receive exactly `gifts_per_person` gifts, never to
+/// themselves, to an excluded recipient, or, when `mode` uses groups, to their own
+/// group. With a single gift each, [`GiftMode::Plain`] and [`GiftMode::Grouped`] also
+/// require one circle through everyone, while [`GiftMode::Derangement`] only requires
+/// every loop to meet its minimum length. Participants missing a group when `mode` uses
+/// groups and exclusions naming someone who is not a participant are reported too.
+/// Returns every violation found.
+pub fn verify(
+    people: &People,
+    assignments: &[Person],
+    mode: GiftMode,
+    gifts_per_person: usize,
+) -> Vec<Violation> {
+    let expected = gifts_per_person.max(1);
+    let index_of: HashMap<&str, usize> = people
+        .iter()
+        .enumerate()
+        .map(|(index, participant)| (participant.name.as_str(), index))
+        .collect();
+    let groups: Vec<Option<String>> = people.iter().map(Participant::group_label).collect();
+
+    let mut violations = Vec::new();
+    if mode.uses_groups() {
+        violations.extend(
+            people
+                .iter()
+                .zip(&groups)
+                .filter(|(_, group)| group.is_none())
+                .map(|(participant, _)| Violation::MissingGroup {
+                    name: participant.name.clone(),
+                }),
+        );
+    }
+    let mut excluded = HashSet::new();
+    for exclusion in people.exclusions() {
+        let giver = index_of.get(exclusion.giver.as_str()).copied();
+        let recipient = index_of.get(exclusion.recipient.as_str()).copied();
+        for (index, name) in [(giver, &exclusion.giver), (recipient, &exclusion.recipient)] {
+            if index.is_none() {
+                violations.push(Violation::UnknownExclusionName { name: name.clone() });
+            }
+        }
+        if let (Some(giver), Some(recipient)) = (giver, recipient) {
+            excluded.insert((giver, recipient));
+        }
+    }
+
+    let mut gives: Vec<Vec<usize>> = vec![Vec::new(); people.len()];
+    let mut receives: Vec<Vec<usize>> = vec![Vec::new(); people.len()];
+    let mut pairs: Vec<((usize, usize), Vec<usize>)> = Vec::new();
+
+    for (position, assignment) in assignments.iter().enumerate() {
+        let row = position + 1;
+        let giver = index_of.get(assignment.name.as_str()).copied();
+        let recipient = index_of
+            .get(assignment.assigned_person_name.as_str())
+            .copied();
+        if giver.is_none() {
+            violations.push(Violation::UnknownGiver {
+                name: assignment.name.clone(),
+                row,
+            });
+        }
+        if recipient.is_none() {
+            violations.push(Violation::UnknownRecipient {
+                name: assignment.assigned_person_name.clone(),
+                row,
+            });
+        }
+        let (Some(giver), Some(recipient)) = (giver, recipient) else {
+            continue;
+        };
+        gives[giver].push(row);
+        receives[recipient].push(row);
+
+        let giver_name = &people[giver].name;
+        let recipient_name = &people[recipient].name;
+        if giver == recipient {
+            violations.push(Violation::SelfAssignment {
+                name: giver_name.clone(),
+                row,
+            });
+            continue;
+        }
+        if let (true, Some(group), Some(recipient_group)) =
+            (mode.uses_groups(), &groups[giver], &groups[recipient])
+        {
+            if same_group(group, recipient_group) {
+                violations.push(Violation::SameGroup {
+                    giver: giver_name.clone(),
+                    recipient: recipient_name.clone(),
+                    group: group.clone(),
+                    row,
+                });
+            }
+        }
+        if excluded.contains(&(giver, recipient)) {
+            violations.push(Violation::Excluded {
+                giver: giver_name.clone(),
+                recipient: recipient_name.clone(),
+                row,
+            });
+        }
+        match pairs
+            .iter_mut()
+            .find(|(pair, _)| *pair == (giver, recipient))
+        {
+            Some((_, rows)) => rows.push(row),
+            None => pairs.push(((giver, recipient), vec![row])),
+        }
+    }
+
+    violations.extend(pairs.into_iter().filter(|(_, rows)| rows.len() > 1).map(
+        |((giver, recipient), rows)| Violation::RepeatedPair {
+            giver: people[giver].name.clone(),
+            recipient: people[recipient].name.clone(),
+            rows,
+        },
+    ));
+
+    let mut counts_ok = true;
+    for (index, participant) in people.iter().enumerate() {
+        if gives[index].len() != expected {
+            counts_ok = false;
+            violations.push(Violation::WrongGiftCount {
+                name: participant.name.clone(),
+                count: gives[index].len(),
+                expected,
+                rows: gives[index].clone(),
+            });
+        }
+        if receives[index].len() != expected {
+            counts_ok = false;
+            violations.push(Violation::WrongReceiveCount {
+                name: participant.name.clone(),
+                count: receives[index].len(),
+                expected,
+                rows: receives[index].clone(),
+            });
+        }
+    }
+
+    if counts_ok && expected == 1 {
+        let mut recipients = vec![0; people.len()];
+        for assignment in assignments {
+            if let (Some(&giver), Some(&recipient)) = (
+                index_of.get(assignment.name.as_str()),
+                index_of.get(assignment.assigned_person_name.as_str()),
+            ) {
+                recipients[giver] = recipient;
+            }
+        }
+        let names = |cycle: &Vec<usize>| -> Vec<String> {
+            cycle
+                .iter()
+                .map(|&index| people[index].name.clone())
+                .collect()
+        };
+        let cycles = cycles_from_recipients(&recipients);
+        match mode.min_cycle_length() {
+            None if cycles.len() > 1 => violations.push(Violation::NotOneCircle {
+                loops: cycles.iter().map(names).collect(),
+            }),
+            None => {}
+            Some(min_cycle_length) => violations.extend(
+                cycles
+                    .iter()
+                    .filter(|cycle| cycle.len() > 1 && cycle.len() < min_cycle_length)
+                    .map(|cycle| Violation::ShortLoop {
+                        names: names(cycle),
+                        min_cycle_length,
+                    }),
+            ),
+        }
+    }
+
+    violations
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::expect_used)]
+mod tests {
+    use super::*;
+
+    fn family() -> People {
+        People::from(vec![
+            Participant::new("Father", 1),
+            Participant::new("Mother", 1),
+            Participant::new("Son", 2),
+            Participant::new("Daughter", 2),
+        ])
+    }
+
+    fn rows(pairs: &[(&str, &str)]) -> Vec<Person> {
+        let people = family();
+        pairs
+            .iter()
+            .map(|(giver, recipient)| {
+                let participant = people
+                    .iter()
+                    .find(|participant| participant.name == *giver)
+                    .cloned()
+                    .unwrap_or_else(|| Participant::new_no_group(giver));
+                Person::from_assignment(&participant, &Participant::new_no_group(recipient))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn accepts_valid_circle() {
+        let assignments = rows(&[
+            ("Father", "Son"),
+            ("Son", "Mother"),
+            ("Mother", "Daughter"),
+            ("Daughter", "Father"),
+        ]);
+        assert!(verify(&family(), &assignments, GiftMode::Grouped, 1).is_empty());
+    }
+
+    #[test]
+    fn reports_each_violation_with_rows() {
+        let mut people = family();
+        people.exclude("Son", "Father");
+        let assignments = rows(&[
+            ("Father", "Mother"),
+            ("Son", "Father"),
+            ("Mother", "Daughter"),
+            ("Daughter", "Daughter"),
+            ("Uncle", "Son"),
+        ]);
+        assert_eq!(
+            verify(&people, &assignments, GiftMode::Grouped, 1),
+            vec![
+                Violation::SameGroup {
+                    giver: "Father".to_string(),
+                    recipient: "Mother".to_string(),
+                    group: "1".to_string(),
+                    row: 1,
+                },
+                Violation::Excluded {
+                    giver: "Son".to_string(),
+                    recipient: "Father".to_string(),
+                    row: 2,
+                },
+                Violation::SelfAssignment {
+                    name: "Daughter".to_string(),
+                    row: 4,
+                },
+                Violation::UnknownGiver {
+                    name: "Uncle".to_string(),
+                    row: 5,
+                },
+                Violation::WrongReceiveCount {
+                    name: "Son".to_string(),
+                    count: 0,
+                    expected: 1,
+                    rows: vec![],
+                },
+                Violation::WrongReceiveCount {
+                    name: "Daughter".to_string(),
+                    count: 2,
+                    expected: 1,
+                    rows: vec![3, 4],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn requires_one_circle_unless_deranged() {
+        let assignments = rows(&[
+            ("Father", "Son"),
+            ("Son", "Father"),
+            ("Mother", "Daughter"),
+            ("Daughter", "Mother"),
+        ]);
+        let violations = verify(&family(), &assignments, GiftMode::Grouped, 1);
+        assert_eq!(
+            violations[0].to_string(),
+            "assignments form 2 separate loops instead of one circle: \
+             Father -> Son; Mother -> Daughter"
+        );
+
+        let swaps_allowed = GiftMode::Derangement {
+            use_groups: true,
+            min_cycle_length: 2,
+        };
+        assert!(verify(&family(), &assignments, swaps_allowed, 1).is_empty());
+        let no_swaps = GiftMode::Derangement {
+            use_groups: true,
+            min_cycle_length: 3,
+        };
+        assert_eq!(verify(&family(), &assignments, no_swaps, 1).len(), 2);
+    }
+
+    #[test]
+    fn checks_known_exclusions_when_one_names_a_stranger() {
+        let mut people = family();
+        people.exclude("Uncle", "Father");
+        people.exclude("Son", "Mother");
+        let assignments = rows(&[
+            ("Father", "Son"),
+            ("Son", "Mother"),
+            ("Mother", "Daughter"),
+            ("Daughter", "Father"),
+        ]);
+        assert_eq!(
+            verify(&people, &assignments, GiftMode::Grouped, 1),
+            vec![
+                Violation::UnknownExclusionName {
+                    name: "Uncle".to_string(),
+                },
+                Violation::Excluded {
+                    giver: "Son".to_string(),
+                    recipient: "Mother".to_string(),
+                    row: 2,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_missing_groups_and_still_checks_the_rest() {
+        let people = People::from(vec![
+            Participant::new("Father", 1),
+            Participant::new("Mother", 1),
+            Participant::new_no_group("Son"),
+            Participant::new("Daughter", 2),
+        ]);
+        let assignments = rows(&[
+            ("Father", "Mother"),
+            ("Mother", "Son"),
+            ("Son", "Daughter"),
+            ("Daughter", "Father"),
+        ]);
+        assert_eq!(
+            verify(&people, &assignments, GiftMode::Grouped, 1),
+            vec![
+                Violation::MissingGroup {
+                    name: "Son".to_string(),
+                },
+                Violation::SameGroup {
+                    giver: "Father".to_string(),
+                    recipient: "Mother".to_string(),
+                    group: "1".to_string(),
+                    row: 1,
+                },
+            ]
+        );
+        assert!(verify(&people, &assignments, GiftMode::Plain, 1).is_empty());
+    }
+}
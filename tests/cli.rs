@@ -2,6 +2,9 @@
 
 use std::process::Command;
 
+const HEADER: &str = "name,email_address,group_number,assigned_person_name,group,\
+                      recipient_wishlist,recipient_sizes,recipient_interests,recipient_do_not_buy";
+
 #[test]
 fn runs_without_groups_example_csv() {
     let output = Command::new(env!("CARGO_BIN_EXE_gift_circle"))
@@ -21,10 +24,7 @@ fn runs_without_groups_example_csv() {
 
     let stdout = String::from_utf8(output.stdout).expect("valid utf-8 stdout");
     let mut lines = stdout.lines();
-    assert_eq!(
-        lines.next(),
-        Some("name,email_address,group_number,assigned_person_name")
-    );
+    assert_eq!(lines.next(), Some(HEADER));
     assert_eq!(lines.count(), 9);
 }
 
@@ -50,9 +50,991 @@ fn runs_with_groups_example_csv() {
 
     let stdout = String::from_utf8(output.stdout).expect("valid utf-8 stdout");
     let mut lines = stdout.lines();
+    assert_eq!(lines.next(), Some(HEADER));
+    assert_eq!(lines.count(), 9);
+}
+
+#[test]
+fn runs_with_groups_and_exclusions_example_csv() {
+    let data_dir = env!("CARGO_MANIFEST_DIR").to_string() + "/data";
+    let output = Command::new(env!("CARGO_BIN_EXE_gift_circle"))
+        .args([
+            "-u",
+            &format!("-i={data_dir}/example-participants-with-groups.csv"),
+            &format!("-x={data_dir}/example-exclusions.csv"),
+        ])
+        .output()
+        .expect("failed to run gift_circle binary");
+
+    assert!(
+        output.status.success(),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let stdout = String::from_utf8(output.stdout).expect("valid utf-8 stdout");
+    assert_eq!(stdout.lines().count(), 10);
+    for line in stdout.lines() {
+        assert!(!line.starts_with("Joe Hill,") || !line.ends_with(",Jack Brown,,,,,"));
+        assert!(!line.starts_with("Jack Brown,") || !line.ends_with(",Joe Hill,,,,,"));
+        assert!(!line.starts_with("Bill Jones,") || !line.ends_with(",Jessica Brown,,,,,"));
+    }
+}
+
+#[test]
+fn runs_with_groups_and_history_example_csv() {
+    let data_dir = env!("CARGO_MANIFEST_DIR").to_string() + "/data";
+    let history = std::fs::read_to_string(format!("{data_dir}/example-history-last-year.csv"))
+        .expect("readable history fixture");
+    let output = Command::new(env!("CARGO_BIN_EXE_gift_circle"))
+        .args([
+            "-u",
+            &format!("-i={data_dir}/example-participants-with-groups.csv"),
+            &format!("--history={data_dir}/example-history-last-year.csv"),
+        ])
+        .output()
+        .expect("failed to run gift_circle binary");
+
+    assert!(
+        output.status.success(),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let stdout = String::from_utf8(output.stdout).expect("valid utf-8 stdout");
+    assert_eq!(stdout.lines().count(), 10);
+    for line in stdout.lines().skip(1) {
+        assert!(!history.lines().any(|previous| previous == line), "{line}");
+    }
+}
+
+#[test]
+fn runs_with_json_input_and_output() {
+    let output = Command::new(env!("CARGO_BIN_EXE_gift_circle"))
+        .args([
+            "-u",
+            "--output-format=json",
+            &format!(
+                "-i={}",
+                env!("CARGO_MANIFEST_DIR").to_string()
+                    + "/data/example-participants-with-groups.json"
+            ),
+        ])
+        .output()
+        .expect("failed to run gift_circle binary");
+
+    assert!(
+        output.status.success(),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let document: serde_json::Value =
+        serde_json::from_slice(&output.stdout).expect("valid json stdout");
+    assert_eq!(document["used_groups"], true);
+    assert!(document["attempts"].as_u64().unwrap() >= 1);
+    assert_eq!(document["people"].as_array().unwrap().len(), 9);
+}
+
+#[test]
+fn runs_with_named_groups_example_csv() {
+    let output = Command::new(env!("CARGO_BIN_EXE_gift_circle"))
+        .args([
+            "-u",
+            &format!(
+                "-i={}",
+                env!("CARGO_MANIFEST_DIR").to_string()
+                    + "/data/example-participants-with-named-groups.csv"
+            ),
+        ])
+        .output()
+        .expect("failed to run gift_circle binary");
+
+    assert!(
+        output.status.success(),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let stdout = String::from_utf8(output.stdout).expect("valid utf-8 stdout");
+    let mut lines = stdout.lines();
+    assert_eq!(lines.next(), Some(HEADER));
+    let rows: Vec<&str> = lines.collect();
+    assert_eq!(rows.len(), 9);
+    assert!(rows.iter().all(|row| row.contains(" family,")));
+}
+
+#[test]
+fn writes_sealed_files_to_output_dir() {
+    let dir = tempfile::tempdir().expect("temporary directory");
+    let output = Command::new(env!("CARGO_BIN_EXE_gift_circle"))
+        .args([
+            "-u",
+            &format!(
+                "-i={}",
+                env!("CARGO_MANIFEST_DIR").to_string()
+                    + "/data/example-participants-with-groups.csv"
+            ),
+            &format!("--output-dir={}", dir.path().display()),
+        ])
+        .output()
+        .expect("failed to run gift_circle binary");
+
+    assert!(
+        output.status.success(),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(output.stdout.is_empty());
+    assert!(!String::from_utf8_lossy(&output.stderr).contains("Using seed"));
+
+    let sealed = std::fs::read_to_string(dir.path().join("joe-hill.csv")).expect("sealed file");
+    let mut lines = sealed.lines();
+    assert_eq!(lines.next(), Some(HEADER));
+    assert!(lines.next().unwrap().starts_with("Joe Hill,"));
+    assert_eq!(lines.next(), None);
+    assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 9);
+}
+
+#[test]
+fn event_details_reach_output_and_sealed_files() {
+    let dir = tempfile::tempdir().expect("temporary directory");
+    let output = Command::new(env!("CARGO_BIN_EXE_gift_circle"))
+        .args([
+            "-u",
+            &format!(
+                "-i={}",
+                env!("CARGO_MANIFEST_DIR").to_string()
+                    + "/data/example-participants-with-groups.csv"
+            ),
+            &format!("--output-dir={}", dir.path().display()),
+            "--print-all",
+            "--budget=30",
+            "--currency=USD",
+            "--group-budget=1=20",
+            "--date=December 24",
+        ])
+        .output()
+        .expect("failed to run gift_circle binary");
+    assert!(
+        output.status.success(),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.ends_with(
+        "# Spending limit: 30 USD\n# Spending limit for 1: 20 USD\n# Date: December 24\n"
+    ));
+
+    let joe = std::fs::read_to_string(dir.path().join("joe-hill.csv")).unwrap();
+    assert!(joe.ends_with("# Spending limit: 20 USD\n# Date: December 24\n"));
+    let jack = std::fs::read_to_string(dir.path().join("jack-brown.csv")).unwrap();
+    assert!(jack.ends_with("# Spending limit: 30 USD\n# Date: December 24\n"));
+}
+
+#[test]
+fn encrypts_sealed_files_and_reveals_one() {
+    use age::secrecy::ExposeSecret;
+
+    let dir = tempfile::tempdir().expect("temporary directory");
+    let names = ["Joe Hill", "Jane Hill", "Jack Brown", "Jessica Brown"];
+    let identities: Vec<age::x25519::Identity> = names
+        .iter()
+        .map(|_| age::x25519::Identity::generate())
+        .collect();
+
+    let mut participants = String::from("name,group_number,public_key\n");
+    for (index, (name, identity)) in names.iter().zip(&identities).enumerate() {
+        participants.push_str(&format!(
+            "{name},{},{}\n",
+            index / 2 + 1,
+            identity.to_public()
+        ));
+    }
+    let input = dir.path().join("participants.csv");
+    std::fs::write(&input, participants).unwrap();
+    let identity_file = dir.path().join("joe.key");
+    std::fs::write(&identity_file, identities[0].to_string().expose_secret()).unwrap();
+    let sealed_dir = dir.path().join("sealed");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_gift_circle"))
+        .args([
+            "-u",
+            &format!("-i={}", input.display()),
+            &format!("--output-dir={}", sealed_dir.display()),
+            "--encrypt",
+        ])
+        .output()
+        .expect("failed to run gift_circle binary");
+    assert!(
+        output.status.success(),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let sealed_file = sealed_dir.join("joe-hill.csv.age");
+    let sealed = std::fs::read_to_string(&sealed_file).unwrap();
+    assert!(!sealed.contains("Joe Hill"));
+
+    let output = Command::new(env!("CARGO_BIN_EXE_gift_circle"))
+        .args([
+            "reveal",
+            &sealed_file.display().to_string(),
+            &format!("--identity={}", identity_file.display()),
+        ])
+        .output()
+        .expect("failed to run gift_circle binary");
+    assert!(
+        output.status.success(),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let revealed = String::from_utf8(output.stdout).unwrap();
+    let row = revealed.lines().nth(1).unwrap();
+    assert!(row.starts_with("Joe Hill,"));
+    assert!(row.ends_with(",Jack Brown,,,,,") || row.ends_with(",Jessica Brown,,,,,"));
+}
+
+/// Minimal SMTP server that accepts `expected` messages, one connection at a time, and
+/// returns their raw DATA sections.
+fn smtp_stand_in(expected: usize) -> (u16, std::thread::JoinHandle<Vec<String>>) {
+    use std::io::{BufRead, BufReader, Write};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let handle = std::thread::spawn(move || {
+        let mut messages = Vec::new();
+        while messages.len() < expected {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut writer = stream;
+            writer.write_all(b"220 localhost ESMTP\r\n").unwrap();
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 0 {
+                let command = line.trim_end().to_ascii_uppercase();
+                if command.starts_with("DATA") {
+                    writer.write_all(b"354 go ahead\r\n").unwrap();
+                    let mut message = String::new();
+                    loop {
+                        let mut data = String::new();
+                        reader.read_line(&mut data).unwrap();
+                        if data == ".\r\n" {
+                            break;
+                        }
+                        message.push_str(&data);
+                    }
+                    messages.push(message);
+                    writer.write_all(b"250 queued\r\n").unwrap();
+                } else if command.starts_with("QUIT") {
+                    writer.write_all(b"221 bye\r\n").unwrap();
+                    break;
+                } else {
+                    writer.write_all(b"250 ok\r\n").unwrap();
+                }
+                line.clear();
+            }
+        }
+        messages
+    });
+    (port, handle)
+}
+
+#[test]
+fn sends_assignments_to_local_smtp_server() {
+    let (port, server) = smtp_stand_in(9);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_gift_circle"))
+        .args([
+            "send",
+            &format!(
+                "-i={}",
+                env!("CARGO_MANIFEST_DIR").to_string() + "/data/example-history-last-year.csv"
+            ),
+            "--from=Santa <santa@example.com>",
+            "--smtp-host=127.0.0.1",
+            &format!("--smtp-port={port}"),
+            "--smtp-security=none",
+            "--subject=Secret for {name}",
+        ])
+        .output()
+        .expect("failed to run gift_circle binary");
+    assert!(
+        output.status.success(),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let report = String::from_utf8(output.stdout).unwrap();
+    let mut lines = report.lines();
+    assert_eq!(lines.next(), Some("name,email_address,status,detail"));
+    assert!(lines.all(|line| line.contains(",sent,")));
+
+    let messages = server.join().unwrap();
+    assert_eq!(messages.len(), 9);
+    let joe = messages
+        .iter()
+        .find(|message| message.contains("Subject: Secret for Joe Hill"))
+        .unwrap();
+    assert!(joe.contains("To: \"Joe Hill\" <joe.hill@example.com>"));
+    assert!(joe.contains("You are getting a gift for Beverly Jones."));
+}
+
+#[test]
+fn dry_run_writes_eml_files() {
+    let dir = tempfile::tempdir().expect("temporary directory");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_gift_circle"))
+        .args([
+            "send",
+            &format!(
+                "-i={}",
+                env!("CARGO_MANIFEST_DIR").to_string() + "/data/example-history-last-year.csv"
+            ),
+            "--from=santa@example.com",
+            "--dry-run",
+            &format!("--eml-dir={}", dir.path().display()),
+        ])
+        .output()
+        .expect("failed to run gift_circle binary");
+    assert!(
+        output.status.success(),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let eml = std::fs::read_to_string(dir.path().join("joe-hill.eml")).unwrap();
+    assert!(eml.contains("Beverly Jones"));
+    assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 9);
+}
+
+#[test]
+fn same_seed_repeats_the_draw() {
+    let run = |extra: &[&str]| {
+        let output = Command::new(env!("CARGO_BIN_EXE_gift_circle"))
+            .args([
+                "-u",
+                &format!(
+                    "-i={}",
+                    env!("CARGO_MANIFEST_DIR").to_string()
+                        + "/data/example-participants-with-groups.csv"
+                ),
+                "--seed=2024",
+            ])
+            .args(extra)
+            .output()
+            .expect("failed to run gift_circle binary");
+        assert!(
+            output.status.success(),
+            "stderr: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    let csv = run(&["--metadata-trailer"]);
+    assert_eq!(csv, run(&["--metadata-trailer"]));
+    assert!(csv.contains("\n# seed: 2024\n"));
+    assert!(csv.ends_with("# mode: grouped\n"));
+
+    let json: serde_json::Value = serde_json::from_str(&run(&["--output-format=json"])).unwrap();
+    assert_eq!(json["metadata"]["seed"], 2024);
+    assert_eq!(json["metadata"]["mode"]["type"], "grouped");
+
+    let uniform = run(&["--metadata-trailer", "--sampling=uniform"]);
+    assert_eq!(uniform, run(&["--metadata-trailer", "--sampling=uniform"]));
+    assert!(uniform.ends_with("# mode: grouped\n# sampling: uniform\n"));
+}
+
+#[test]
+fn validate_reports_all_problems_with_line_numbers() {
+    let dir = tempfile::tempdir().expect("temporary directory");
+    let input = dir.path().join("participants.csv");
+    std::fs::write(
+        &input,
+        "name,email_address,group_number\n\
+         Joe Hill,joe.hill@example.com,1\n\
+         Jane Hill,jane.hill@example,one\n\
+         Joe Hill,not an email,2\n\
+         ,,2\n\
+         Jack Brown,,\n",
+    )
+    .unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_gift_circle"))
+        .args(["validate", "-u", &format!("-i={}", input.display())])
+        .output()
+        .expect("failed to run gift_circle binary");
+    assert!(!output.status.success());
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 5, "stdout: {stdout}");
+    assert!(lines[0].starts_with("line 3: could not read participant"));
     assert_eq!(
-        lines.next(),
-        Some("name,email_address,group_number,assigned_person_name")
+        lines[1],
+        "line 4: \"Joe Hill\" has a malformed email address \"not an email\""
     );
-    assert_eq!(lines.count(), 9);
+    assert_eq!(lines[2], "line 5: name is blank");
+    assert_eq!(lines[3], "line 6: \"Jack Brown\" has no group");
+    assert_eq!(
+        lines[4],
+        "line 2, line 4: name \"Joe Hill\" is used more than once"
+    );
+}
+
+#[test]
+fn validate_accepts_example_csv() {
+    let output = Command::new(env!("CARGO_BIN_EXE_gift_circle"))
+        .args([
+            "validate",
+            "-u",
+            &format!(
+                "-i={}",
+                env!("CARGO_MANIFEST_DIR").to_string()
+                    + "/data/example-participants-with-groups.csv"
+            ),
+        ])
+        .output()
+        .expect("failed to run gift_circle binary");
+    assert!(
+        output.status.success(),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+#[test]
+fn generate_subcommand_matches_top_level_options() {
+    let input = format!(
+        "-i={}",
+        env!("CARGO_MANIFEST_DIR").to_string() + "/data/example-participants-with-groups.csv"
+    );
+    let run = |args: &[&str]| {
+        let output = Command::new(env!("CARGO_BIN_EXE_gift_circle"))
+            .args(args)
+            .output()
+            .expect("failed to run gift_circle binary");
+        assert!(
+            output.status.success(),
+            "stderr: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    assert_eq!(
+        run(&["generate", "-u", &input, "--seed=7"]),
+        run(&["-u", &input, "--seed=7"])
+    );
+}
+
+#[test]
+fn stats_summarises_groups() {
+    let output = Command::new(env!("CARGO_BIN_EXE_gift_circle"))
+        .args([
+            "stats",
+            &format!(
+                "-i={}",
+                env!("CARGO_MANIFEST_DIR").to_string()
+                    + "/data/example-participants-with-named-groups.csv"
+            ),
+        ])
+        .output()
+        .expect("failed to run gift_circle binary");
+    assert!(
+        output.status.success(),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with("participants: 9\ngroups: 3\n  Jones family: 4\n"));
+}
+
+#[test]
+fn stats_finds_circles_too_rare_to_estimate() {
+    let dir = tempfile::tempdir().expect("temporary directory");
+    let participants = dir.path().join("participants.csv");
+    let mut csv = String::from("name,email_address,group_number\n");
+    for index in 0..30 {
+        csv.push_str(&format!("p{index},,{}\n", index % 2 + 1));
+    }
+    std::fs::write(&participants, csv).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_gift_circle"))
+        .args([
+            "stats",
+            "-u",
+            "--seed=1",
+            "--draws=0",
+            &format!("-i={}", participants.display()),
+        ])
+        .output()
+        .expect("failed to run gift_circle binary");
+    assert!(
+        output.status.success(),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout.contains("\npossible circles: at least 1 "),
+        "{stdout}"
+    );
+}
+
+#[test]
+fn stats_counts_circles_and_pairing_chances() {
+    let output = Command::new(env!("CARGO_BIN_EXE_gift_circle"))
+        .args([
+            "stats",
+            "-u",
+            "--seed=1",
+            "--draws=200",
+            "--giver=Joe Hill",
+            &format!(
+                "-i={}",
+                env!("CARGO_MANIFEST_DIR").to_string()
+                    + "/data/example-participants-with-named-groups.csv"
+            ),
+        ])
+        .output()
+        .expect("failed to run gift_circle binary");
+    assert!(
+        output.status.success(),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout.contains("\npossible circles: 1728\nchance of each pairing over 200 fast draws:\n")
+    );
+    let chances: Vec<&str> = stdout
+        .lines()
+        .filter(|line| line.contains(" -> "))
+        .collect();
+    assert_eq!(chances.len(), 6);
+    assert!(chances
+        .iter()
+        .all(|line| line.starts_with("  Joe Hill -> ")));
+    assert!(!stdout.contains("Hill -> Jane Hill"));
+}
+
+#[test]
+fn simulate_reports_pair_counts_attempts_and_group_bias() {
+    let dir = tempfile::tempdir().expect("temporary directory");
+    let input =
+        env!("CARGO_MANIFEST_DIR").to_string() + "/data/example-participants-with-named-groups.csv";
+    let run = |args: &[&str]| {
+        let output = Command::new(env!("CARGO_BIN_EXE_gift_circle"))
+            .args([
+                "simulate",
+                "-u",
+                "--draws=50",
+                "--seed=3",
+                &format!("-i={input}"),
+            ])
+            .args(args)
+            .output()
+            .expect("failed to run gift_circle binary");
+        assert!(
+            output.status.success(),
+            "stderr: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    let report_path = dir.path().join("report.json");
+    run(&[&format!("-o={}", report_path.display())]);
+    let report: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&report_path).unwrap()).unwrap();
+    assert_eq!(report["draws"], 50);
+    assert_eq!(report["names"].as_array().unwrap().len(), 9);
+    for row in report["pair_counts"].as_array().unwrap() {
+        let total: u64 = row
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter_map(|count| count.as_u64())
+            .sum();
+        assert_eq!(total, 50);
+    }
+    let draws: u64 = report["attempts"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter_map(|count| count["draws"].as_u64())
+        .sum();
+    assert_eq!(draws, 50);
+    assert_eq!(report["groups"].as_array().unwrap().len(), 6);
+
+    let csv = run(&[]);
+    let tables: Vec<&str> = csv.split("\n\n").collect();
+    assert_eq!(tables.len(), 3);
+    assert!(tables[0].starts_with("giver,Joe Hill,Jane Hill,"));
+    assert!(tables[1].starts_with("attempts,draws\n"));
+    assert!(tables[2].starts_with("giver_group,recipient_group,gifts,share,allowed_share,bias\n"));
+}
+
+#[test]
+fn verify_checks_hand_edited_assignments() {
+    let history = env!("CARGO_MANIFEST_DIR").to_string() + "/data/example-history-last-year.csv";
+    let output = Command::new(env!("CARGO_BIN_EXE_gift_circle"))
+        .args(["verify", "-u", &format!("-i={history}")])
+        .output()
+        .expect("failed to run gift_circle binary");
+    assert!(
+        output.status.success(),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let dir = tempfile::tempdir().expect("temporary directory");
+    let edited = dir.path().join("edited.csv");
+    let contents = std::fs::read_to_string(&history).unwrap().replace(
+        "Joe Hill,joe.hill@example.com,1,Beverly Jones",
+        "Joe Hill,joe.hill@example.com,1,Kenya Hill",
+    );
+    std::fs::write(&edited, contents).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_gift_circle"))
+        .args(["verify", "-u", &format!("-i={}", edited.display())])
+        .output()
+        .expect("failed to run gift_circle binary");
+    assert!(!output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(
+        lines,
+        vec![
+            "row 2: \"Joe Hill\" gives to \"Kenya Hill\" from their own group \"1\"",
+            "\"Beverly Jones\" receives 0 gifts instead of 1",
+            "\"Kenya Hill\" receives 2 gifts instead of 1 (rows 2, 7)",
+        ]
+    );
+}
+
+#[test]
+fn repair_adds_late_joiner_and_keeps_other_pairs() {
+    let data = env!("CARGO_MANIFEST_DIR").to_string() + "/data";
+    let dir = tempfile::tempdir().expect("temporary directory");
+    let participants = dir.path().join("participants.csv");
+    let mut contents =
+        std::fs::read_to_string(format!("{data}/example-participants-with-groups.csv")).unwrap();
+    contents = contents.trim_end().to_string() + "\nCousin Itt,itt@example.com,4\n";
+    std::fs::write(&participants, contents).unwrap();
+    let repaired = dir.path().join("repaired.csv");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_gift_circle"))
+        .args([
+            "repair",
+            "-u",
+            &format!("-i={data}/example-history-last-year.csv"),
+            &format!("--participants={}", participants.display()),
+            &format!("--output={}", repaired.display()),
+            "--seed=3",
+        ])
+        .output()
+        .expect("failed to run gift_circle binary");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "stderr: {stderr}");
+    assert!(stderr.contains("#INFO: 8 of 10 givers keep their recipient"));
+    assert!(stderr.contains("#INFO: Tell Cousin Itt about their new recipient"));
+
+    let output = Command::new(env!("CARGO_BIN_EXE_gift_circle"))
+        .args([
+            "verify",
+            "-u",
+            &format!("-i={}", repaired.display()),
+            &format!("--participants={}", participants.display()),
+        ])
+        .output()
+        .expect("failed to run gift_circle binary");
+    assert!(
+        output.status.success(),
+        "stdout: {}",
+        String::from_utf8_lossy(&output.stdout)
+    );
+}
+
+#[test]
+fn init_writes_config_that_drives_the_draw() {
+    let dir = tempfile::tempdir().expect("temporary directory");
+    let participants = dir.path().join("participants.csv");
+    std::fs::copy(
+        env!("CARGO_MANIFEST_DIR").to_string() + "/data/example-participants-with-groups.csv",
+        &participants,
+    )
+    .unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_gift_circle"))
+        .args(["init", &format!("-i={}", participants.display())])
+        .output()
+        .expect("failed to run gift_circle binary");
+    assert!(
+        output.status.success(),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let config = dir.path().join("gift_circle.toml");
+    let starter = std::fs::read_to_string(&config).unwrap();
+    assert!(starter.contains("input = \"participants.csv\""));
+    std::fs::write(
+        &config,
+        starter
+            .replace("use_groups = false", "use_groups = true")
+            .replace("seed = \"random\"", "seed = 11")
+            .replace(
+                "# output = \"assignments.csv\"",
+                "output = \"assignments.csv\"",
+            ),
+    )
+    .unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_gift_circle"))
+        .current_dir(dir.path())
+        .output()
+        .expect("failed to run gift_circle binary");
+    assert!(
+        output.status.success(),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let from_config = std::fs::read_to_string(dir.path().join("assignments.csv")).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_gift_circle"))
+        .args(["-u", &format!("-i={}", participants.display()), "--seed=11"])
+        .output()
+        .expect("failed to run gift_circle binary");
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), from_config);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_gift_circle"))
+        .current_dir(dir.path())
+        .arg("--no-use-groups")
+        .output()
+        .expect("failed to run gift_circle binary");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("NOT USING groups"));
+    let without_groups = std::fs::read_to_string(dir.path().join("assignments.csv")).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_gift_circle"))
+        .args([&format!("-i={}", participants.display()), "--seed=11"])
+        .output()
+        .expect("failed to run gift_circle binary");
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), without_groups);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_gift_circle"))
+        .args(["init", &format!("-i={}", participants.display())])
+        .output()
+        .expect("failed to run gift_circle binary");
+    assert!(!output.status.success());
+}
+
+#[test]
+fn committed_draw_passes_audit_with_shuffled_input() {
+    let dir = tempfile::tempdir().expect("temporary directory");
+    let data_dir = env!("CARGO_MANIFEST_DIR").to_string() + "/data";
+    let participants = format!("{data_dir}/example-participants-with-groups.csv");
+    let secret_file = dir.path().join("draw.secret");
+    let assignments = dir.path().join("assignments.csv");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_gift_circle"))
+        .args([
+            "commit",
+            "-u",
+            &format!("-i={participants}"),
+            &format!("--secret-file={}", secret_file.display()),
+        ])
+        .output()
+        .expect("failed to run gift_circle binary");
+    assert!(
+        output.status.success(),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let commitment = String::from_utf8(output.stdout).unwrap().trim().to_string();
+    assert_eq!(commitment.len(), 64);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_gift_circle"))
+        .args([
+            "-u",
+            &format!("-i={participants}"),
+            &format!("--secret-file={}", secret_file.display()),
+            "--entropy=snowman",
+            "--entropy=1225",
+            &format!("-o={}", assignments.display()),
+        ])
+        .output()
+        .expect("failed to run gift_circle binary");
+    assert!(
+        output.status.success(),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(String::from_utf8_lossy(&output.stderr).contains(&commitment));
+
+    let original = std::fs::read_to_string(&participants).unwrap();
+    let mut lines: Vec<&str> = original.lines().collect();
+    lines[1..].reverse();
+    let shuffled = dir.path().join("shuffled.csv");
+    std::fs::write(&shuffled, lines.join("\n") + "\n").unwrap();
+
+    let secret = std::fs::read_to_string(&secret_file).unwrap();
+    let audit = |secret: &str| {
+        Command::new(env!("CARGO_BIN_EXE_gift_circle"))
+            .args([
+                "audit",
+                "-u",
+                &format!("-i={}", shuffled.display()),
+                &format!("-a={}", assignments.display()),
+                &format!("--commitment={commitment}"),
+                &format!("--secret={}", secret.trim()),
+                "--entropy=1225",
+                "--entropy=snowman",
+            ])
+            .output()
+            .expect("failed to run gift_circle binary")
+    };
+    let output = audit(&secret);
+    assert!(
+        output.status.success(),
+        "stdout: {}",
+        String::from_utf8_lossy(&output.stdout)
+    );
+
+    let output = audit("not the secret");
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stdout)
+        .unwrap()
+        .starts_with("published commitment"));
+}
+
+#[test]
+fn lookup_codes_find_each_recipient_without_the_full_list() {
+    let dir = tempfile::tempdir().expect("temporary directory");
+    let table = dir.path().join("table.csv");
+    let codes_dir = dir.path().join("codes");
+    let output = Command::new(env!("CARGO_BIN_EXE_gift_circle"))
+        .args([
+            "-u",
+            &format!(
+                "-i={}",
+                env!("CARGO_MANIFEST_DIR").to_string()
+                    + "/data/example-participants-with-groups.csv"
+            ),
+            &format!("--output-dir={}", codes_dir.display()),
+            &format!("--lookup-table={}", table.display()),
+        ])
+        .output()
+        .expect("failed to run gift_circle binary");
+    assert!(
+        output.status.success(),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(output.stdout.is_empty());
+    assert!(std::fs::read_to_string(&table)
+        .unwrap()
+        .starts_with("code_hash,recipient\n"));
+
+    let mut recipients = Vec::new();
+    for entry in std::fs::read_dir(&codes_dir).unwrap() {
+        let contents = std::fs::read_to_string(entry.unwrap().path()).unwrap();
+        let row = contents.lines().nth(1).unwrap();
+        let code = row.rsplit(',').next().unwrap();
+        let output = Command::new(env!("CARGO_BIN_EXE_gift_circle"))
+            .args([
+                "lookup",
+                &format!("--table={}", table.display()),
+                &format!("--code={}", code.to_lowercase()),
+            ])
+            .output()
+            .expect("failed to run gift_circle binary");
+        assert!(output.status.success());
+        recipients.push(String::from_utf8(output.stdout).unwrap().trim().to_string());
+    }
+    recipients.sort();
+    recipients.dedup();
+    assert_eq!(recipients.len(), 9);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_gift_circle"))
+        .args([
+            "lookup",
+            &format!("--table={}", table.display()),
+            "--code=0000-0000-0000-0000",
+        ])
+        .output()
+        .expect("failed to run gift_circle binary");
+    assert!(!output.status.success());
+}
+
+#[test]
+fn stats_pairing_chances_avoid_history() {
+    let dir = tempfile::tempdir().expect("temporary directory");
+    let participants = dir.path().join("participants.csv");
+    std::fs::write(
+        &participants,
+        "name,email_address,group_number\na,,\nb,,\nc,,\n",
+    )
+    .unwrap();
+    let history = dir.path().join("last-year.csv");
+    std::fs::write(
+        &history,
+        "name,email_address,group_number,assigned_person_name\na,,,b\nb,,,c\nc,,,a\n",
+    )
+    .unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_gift_circle"))
+        .args([
+            "stats",
+            "--seed=1",
+            "--draws=20",
+            "--giver=a",
+            &format!("-i={}", participants.display()),
+            &format!("--history={}", history.display()),
+        ])
+        .output()
+        .expect("failed to run gift_circle binary");
+    assert!(
+        output.status.success(),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("\npossible circles: 2\n"), "{stdout}");
+    assert!(stdout.ends_with("\n  a -> c: 100.0%\n"), "{stdout}");
+}
+
+#[test]
+fn stats_estimate_says_how_many_orders_fit() {
+    let dir = tempfile::tempdir().expect("temporary directory");
+    let participants = dir.path().join("participants.csv");
+    let mut csv = String::from("name,email_address,group_number\n");
+    for index in 0..20 {
+        csv.push_str(&format!("p{index},,{}\n", index % 2 + 1));
+    }
+    std::fs::write(&participants, csv).unwrap();
+    let run = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_gift_circle"))
+            .args(["stats", "-u", "--seed=1"])
+            .arg(format!("-i={}", participants.display()))
+            .args(args)
+            .output()
+            .expect("failed to run gift_circle binary")
+    };
+
+    let output = run(&["--draws=0"]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout.contains(" of 100000 random orders fit the rules"),
+        "{stdout}"
+    );
+
+    // Uniform draws cannot cover 20 people, which is reported before any of the summary.
+    let output = run(&["--sampling=uniform"]);
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("too many for uniform sampling"));
 }